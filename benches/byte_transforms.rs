@@ -0,0 +1,61 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use toms_data_onion_rust::layer_one;
+use toms_data_onion_rust::layer_two::{self, ParityScheme};
+use toms_data_onion_rust::rng::Rng;
+use toms_data_onion_rust::transform::{ByteTable, Transform};
+
+const DATA_SIZE: usize = 64 * 1024 * 1024;
+const MIN_DURATION: Duration = Duration::from_secs(1);
+
+/*
+    Compares the scalar, lookup table and SIMD paths of the per byte
+    transforms, run with `cargo bench`.
+*/
+fn main() {
+    // The data only has to be the same on every run
+    let data: Vec<u8> = Rng::new(0).bytes(DATA_SIZE);
+
+    let transform: Transform = layer_one::transform();
+    let table: ByteTable = transform.table();
+    let mut buffer: Vec<u8> = data.clone();
+    bench("layer one, scalar", || transform.apply_scalar(&data));
+    bench("layer one, lookup table", || table.apply(&data));
+    bench("layer one, simd", || transform.apply(&data));
+    bench("layer one, lookup table in place", || {
+        table.apply_in_place(&mut buffer)
+    });
+    bench("layer one, simd in place", || {
+        transform.apply_in_place(&mut buffer)
+    });
+
+    let scheme: ParityScheme = ParityScheme::default();
+    let parity_table: [Option<u8>; 256] = scheme.table();
+    bench("layer two parity, scalar", || {
+        data.iter()
+            .filter(|byte| scheme.check(**byte).is_some())
+            .count()
+    });
+    bench("layer two parity, lookup table", || {
+        data.iter()
+            .filter(|byte| parity_table[**byte as usize].is_some())
+            .count()
+    });
+    let encoded: Vec<u8> = layer_two::encode(&data, scheme);
+    bench("layer two decode", || layer_two::decode(&encoded));
+}
+
+fn bench<T>(name: &str, mut run: impl FnMut() -> T) {
+    black_box(run());
+
+    let mut iterations: u32 = 0;
+    let start: Instant = Instant::now();
+    while start.elapsed() < MIN_DURATION {
+        black_box(run());
+        iterations += 1;
+    }
+    let seconds: f64 = start.elapsed().as_secs_f64() / iterations as f64;
+    let throughput: f64 = DATA_SIZE as f64 / seconds / (1024.0 * 1024.0);
+    println!("{:32} {:10.0} MiB/s", name, throughput);
+}
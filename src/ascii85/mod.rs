@@ -0,0 +1,419 @@
+use crate::encoding::{DecodeError, ErrorKind, Position};
+
+/*
+    Adobe flavoured ASCII85: every group of 4 bytes is written as 5 digits
+    in base 85, from '!' (0) to 'u' (84), and a group of 4 zero bytes may be
+    abbreviated as 'z'. The data can be delimited by '<~' and '~>', and
+    whitespace anywhere is ignored. A final group of 1 to 3 bytes is padded
+    with zero bytes and only its first 2 to 4 digits are written.
+*/
+
+pub(crate) const ALPHABET: &[u8; 85] =
+    b"!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstu";
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    // Only whitespace so far, the data may start with '<~'
+    Start,
+    // A '<' was the first character, it is a digit unless '~' follows
+    StartAngle(Position),
+    Body,
+    // A '~' inside the data, which must be the start of '~>'
+    Tilde(Position),
+    End,
+}
+
+/*
+    Decodes ASCII85 data given in chunks of any size, e.g. as it is read
+    from a file, appending the decoded bytes to an output buffer.
+*/
+pub struct Decoder {
+    state: State,
+    delimited: bool,
+    group: u64,
+    digits: usize,
+    group_start: Position,
+    position: Position,
+}
+
+impl Default for Decoder {
+    fn default() -> Decoder {
+        Decoder::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        let start: Position = Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        };
+        Decoder {
+            state: State::Start,
+            delimited: false,
+            group: 0,
+            digits: 0,
+            group_start: start,
+            position: start,
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8], output: &mut Vec<u8>) -> Result<(), DecodeError> {
+        for &byte in chunk {
+            self.feed_byte(byte, output)?;
+
+            self.position.offset += 1;
+            if byte == b'\n' {
+                self.position.line += 1;
+                self.position.column = 1;
+            } else {
+                self.position.column += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        Decodes the final group, the data must be complete.
+    */
+    pub fn finish(mut self, output: &mut Vec<u8>) -> Result<(), DecodeError> {
+        match self.state {
+            State::StartAngle(position) => self.push_digit(b'<', position, output)?,
+            State::Tilde(position) => {
+                return Err(DecodeError {
+                    kind: ErrorKind::InvalidCharacter(b'~'),
+                    position,
+                })
+            }
+            State::Body if self.delimited => {
+                return Err(DecodeError {
+                    kind: ErrorKind::MissingTerminator,
+                    position: self.position,
+                })
+            }
+            _ => {}
+        }
+        self.flush(output)
+    }
+
+    fn feed_byte(&mut self, byte: u8, output: &mut Vec<u8>) -> Result<(), DecodeError> {
+        let position: Position = self.position;
+        let error = |kind: ErrorKind| Err(DecodeError { kind, position });
+
+        match self.state {
+            State::Start => {
+                if byte == b'<' {
+                    self.state = State::StartAngle(position);
+                    return Ok(());
+                }
+                if !byte.is_ascii_whitespace() {
+                    self.state = State::Body;
+                    return self.feed_byte(byte, output);
+                }
+                Ok(())
+            }
+            State::StartAngle(angle) => {
+                self.state = State::Body;
+                if byte == b'~' {
+                    self.delimited = true;
+                    return Ok(());
+                }
+                self.push_digit(b'<', angle, output)?;
+                self.feed_byte(byte, output)
+            }
+            State::Body => match byte {
+                b'~' => {
+                    self.state = State::Tilde(position);
+                    Ok(())
+                }
+                b'z' if self.digits > 0 => error(ErrorKind::MisplacedZ),
+                b'z' => {
+                    output.extend_from_slice(&[0; 4]);
+                    Ok(())
+                }
+                b'!'..=b'u' => self.push_digit(byte, position, output),
+                _ if byte.is_ascii_whitespace() => Ok(()),
+                _ => error(ErrorKind::InvalidCharacter(byte)),
+            },
+            State::Tilde(tilde) => {
+                if byte != b'>' {
+                    return Err(DecodeError {
+                        kind: ErrorKind::InvalidCharacter(b'~'),
+                        position: tilde,
+                    });
+                }
+                self.state = State::End;
+                self.flush(output)
+            }
+            State::End if byte.is_ascii_whitespace() => Ok(()),
+            State::End => error(ErrorKind::DataAfterTerminator),
+        }
+    }
+
+    fn push_digit(
+        &mut self,
+        digit: u8,
+        position: Position,
+        output: &mut Vec<u8>,
+    ) -> Result<(), DecodeError> {
+        if self.digits == 0 {
+            self.group_start = position;
+        }
+        self.group = self.group * 85 + (digit - b'!') as u64;
+        self.digits += 1;
+        if self.digits == 5 {
+            self.push_group(4, output)?;
+        }
+        Ok(())
+    }
+
+    /*
+        Decodes a final group of 2 to 4 digits by padding it with 'u'.
+    */
+    fn flush(&mut self, output: &mut Vec<u8>) -> Result<(), DecodeError> {
+        match self.digits {
+            0 => Ok(()),
+            1 => Err(DecodeError {
+                kind: ErrorKind::TruncatedGroup,
+                position: self.group_start,
+            }),
+            digits => {
+                for _ in digits..5 {
+                    self.group = self.group * 85 + 84;
+                }
+                self.push_group(digits - 1, output)
+            }
+        }
+    }
+
+    fn push_group(&mut self, length: usize, output: &mut Vec<u8>) -> Result<(), DecodeError> {
+        if self.group > u32::MAX as u64 {
+            return Err(DecodeError {
+                kind: ErrorKind::Overflow,
+                position: self.group_start,
+            });
+        }
+        output.extend_from_slice(&(self.group as u32).to_be_bytes()[..length]);
+        self.group = 0;
+        self.digits = 0;
+        Ok(())
+    }
+}
+
+pub fn decode(encoded: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let mut decoder: Decoder = Decoder::new();
+    let mut output: Vec<u8> = Vec::with_capacity(encoded.len() / 5 * 4);
+    decoder.feed(encoded, &mut output)?;
+    decoder.finish(&mut output)?;
+    Ok(output)
+}
+
+/*
+    How `encode` writes its output. The payloads of the onion are delimited,
+    wrapped at 60 columns with the delimiters counted, and never need 'z'.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EncodeOptions {
+    pub delimiters: bool,
+    pub compress_zeros: bool,
+    // 0 writes everything on a single line
+    pub line_width: usize,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> EncodeOptions {
+        EncodeOptions {
+            delimiters: true,
+            compress_zeros: true,
+            line_width: 60,
+        }
+    }
+}
+
+pub fn encode(data: &[u8], options: &EncodeOptions) -> Vec<u8> {
+    let mut writer: LineWriter = LineWriter {
+        output: Vec::with_capacity(data.len() / 4 * 5 + data.len() / 48 + 8),
+        column: 0,
+        line_width: options.line_width,
+    };
+    if options.delimiters {
+        writer.write(b"<~");
+    }
+
+    for group in data.chunks(4) {
+        if options.compress_zeros && group == [0; 4] {
+            writer.write(b"z");
+            continue;
+        }
+
+        for digit in base85_digits(group, ALPHABET) {
+            writer.write(&[digit]);
+        }
+    }
+
+    if options.delimiters {
+        // The terminator is kept on one line, a '~' alone is invalid
+        writer.write(b"~>");
+    }
+    writer.output
+}
+
+/*
+    The digits of a group of 1 to 4 bytes, a final group of n bytes only
+    needs its first n + 1 digits. Other base 85 encodings use the same
+    digits with another alphabet.
+*/
+pub(crate) fn base85_digits(group: &[u8], alphabet: &[u8; 85]) -> Vec<u8> {
+    let mut padded: [u8; 4] = [0; 4];
+    padded[..group.len()].copy_from_slice(group);
+    let mut value: u32 = u32::from_be_bytes(padded);
+    let mut digits: [u8; 5] = [0; 5];
+    for digit in digits.iter_mut().rev() {
+        *digit = alphabet[(value % 85) as usize];
+        value /= 85;
+    }
+    digits[..group.len() + 1].to_vec()
+}
+
+/*
+    Writes the encoded characters, starting a new line before any that
+    would not fit on the current one.
+*/
+struct LineWriter {
+    output: Vec<u8>,
+    column: usize,
+    line_width: usize,
+}
+
+impl LineWriter {
+    fn write(&mut self, characters: &[u8]) {
+        if self.line_width > 0
+            && self.column > 0
+            && self.column + characters.len() > self.line_width
+        {
+            self.output.push(b'\n');
+            self.column = 0;
+        }
+        self.output.extend_from_slice(characters);
+        self.column += characters.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ascii85::{
+        decode, encode, DecodeError, Decoder, EncodeOptions, ErrorKind, Position,
+    };
+
+    const LEVIATHAN: &[u8] =
+        b"Man is distinguished, not only by his reason, but by this singular passion";
+    const LEVIATHAN_ENCODED: &[u8] =
+        b"<~9jqo^BlbD-BleB1DJ+*+F(f,q/0JhKF<GL>Cj@.4Gp$d7F!,L7@<6@)/0JDEF<G%<+EV:2F!,\n\
+        O<DJ+*.@<*K0@<6L(Df-\\0Ec5e;DffZ(EZee.Bl.9pF\"AGXBPCsi+DGm>@3BB/F*&OCAfu2/AKY\n\
+        i(DIb:@FD,*)+C]U=@3BN#EcYf8ATD3s@q?d$AftVqCh[NqF<G:8+EV:.+Cf>-FD5W8ARlolDIa\n\
+        l(DId<j@<?3r@:F%a+D58'ATD4$Bl@l3De:,-DJs`8ARoFb/0JMK@qB4^F!,R<AKZ&-DfTqBG%G\n\
+        >uD.RTpAKYo'+CT/5+Cei#DII?(E,9)oF*2M7/c~>";
+
+    #[test]
+    fn decode_test() {
+        let decoded: Vec<u8> = decode(LEVIATHAN_ENCODED).unwrap();
+        assert!(decoded.starts_with(LEVIATHAN));
+        assert!(decoded.ends_with(b"carnal pleasure."));
+
+        // Fed one byte at a time
+        let mut decoder: Decoder = Decoder::new();
+        let mut output: Vec<u8> = Vec::new();
+        for byte in LEVIATHAN_ENCODED {
+            decoder.feed(&[*byte], &mut output).unwrap();
+        }
+        decoder.finish(&mut output).unwrap();
+        assert_eq!(decoded, output);
+
+        assert_eq!(b"Man sure.".to_vec(), decode(b"9jqo^F*2M7/c").unwrap());
+        assert_eq!(
+            b"\0\0\0\0Man \0\0\0\0".to_vec(),
+            decode(b" <~z 9jqo^\n\tz ~>\n").unwrap()
+        );
+        assert_eq!(vec![0xFF; 4], decode(b"s8W-!").unwrap());
+        assert_eq!(b"T".to_vec(), decode(b"<*").unwrap());
+        assert_eq!(Vec::<u8>::new(), decode(b"<~~>").unwrap());
+    }
+
+    #[test]
+    fn decode_errors() {
+        let error = |kind: ErrorKind, offset: usize, line: usize, column: usize| DecodeError {
+            kind,
+            position: Position {
+                offset,
+                line,
+                column,
+            },
+        };
+
+        assert_eq!(
+            Err(error(ErrorKind::InvalidCharacter(b'{'), 15, 2, 3)),
+            decode(b"<~9jqo^F*2M7\n/c{~>")
+        );
+        assert_eq!(
+            "invalid character 0x7b at byte 15, line 2, column 3",
+            decode(b"<~9jqo^F*2M7\n/c{~>").unwrap_err().to_string()
+        );
+        assert_eq!(Err(error(ErrorKind::MisplacedZ, 4, 1, 5)), decode(b"<~9jz"));
+        assert_eq!(
+            Err(error(ErrorKind::Overflow, 8, 2, 1)),
+            decode(b"<~9jqo^\ns8W-\"~>")
+        );
+        assert_eq!(
+            Err(error(ErrorKind::TruncatedGroup, 7, 1, 8)),
+            decode(b"<~9jqo^9~>")
+        );
+        assert_eq!(
+            Err(error(ErrorKind::MissingTerminator, 7, 1, 8)),
+            decode(b"<~9jqo^")
+        );
+        assert_eq!(
+            Err(error(ErrorKind::InvalidCharacter(b'~'), 7, 1, 8)),
+            decode(b"<~9jqo^~~>")
+        );
+        assert_eq!(
+            Err(error(ErrorKind::DataAfterTerminator, 10, 2, 1)),
+            decode(b"<~9jqo^~>\n9")
+        );
+    }
+
+    #[test]
+    fn encode_test() {
+        let options: EncodeOptions = EncodeOptions {
+            line_width: 75,
+            ..EncodeOptions::default()
+        };
+        assert_eq!(
+            LEVIATHAN_ENCODED.to_vec(),
+            encode(&decode(LEVIATHAN_ENCODED).unwrap(), &options)
+        );
+
+        let options: EncodeOptions = EncodeOptions {
+            delimiters: false,
+            compress_zeros: false,
+            line_width: 0,
+        };
+        assert_eq!(
+            b"!!!!!9jqo^!!".to_vec(),
+            encode(b"\0\0\0\0Man \0", &options)
+        );
+        assert_eq!(
+            b"<~z9jqo^!!~>".to_vec(),
+            encode(b"\0\0\0\0Man \0", &EncodeOptions::default())
+        );
+
+        // The terminator moves to the next line rather than being split
+        let options: EncodeOptions = EncodeOptions {
+            line_width: 7,
+            ..EncodeOptions::default()
+        };
+        let encoded: Vec<u8> = encode(b"Man sure.", &options);
+        assert_eq!(b"<~9jqo^\nF*2M7/c\n~>".to_vec(), encoded);
+        assert_eq!(b"Man sure.".to_vec(), decode(&encoded).unwrap());
+    }
+}
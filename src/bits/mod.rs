@@ -0,0 +1,230 @@
+/*
+    Bit level reading and writing, for layers that pack values that are not
+    a whole number of bytes.
+
+    With `MsbFirst`, values are written most significant bit first and fill
+    bytes from their most significant bit. With `LsbFirst`, values are
+    written least significant bit first and fill bytes from their least
+    significant bit.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+pub struct BitWriter {
+    order: BitOrder,
+    bytes: Vec<u8>,
+    // Bits not written to `bytes` yet, always fewer than 8 between pushes
+    pending: u64,
+    filled: usize,
+}
+
+impl BitWriter {
+    pub fn new(order: BitOrder) -> BitWriter {
+        BitWriter::with_capacity(order, 0)
+    }
+
+    /*
+        A writer with room for `capacity` bytes before reallocating.
+    */
+    pub fn with_capacity(order: BitOrder, capacity: usize) -> BitWriter {
+        BitWriter {
+            order,
+            bytes: Vec::with_capacity(capacity),
+            pending: 0,
+            filled: 0,
+        }
+    }
+
+    /*
+        Writes the `n` least significant bits of `value`, `n` is at most 64.
+    */
+    pub fn push_bits(&mut self, value: u64, n: usize) {
+        assert!(n <= 64, "Cannot push more than 64 bits at once");
+        // The pending bits and the new ones must fit in 64 bits
+        if n > 56 {
+            match self.order {
+                BitOrder::MsbFirst => {
+                    self.push_bits(value >> 32, n - 32);
+                    self.push_bits(value & 0xFFFF_FFFF, 32);
+                }
+                BitOrder::LsbFirst => {
+                    self.push_bits(value & 0xFFFF_FFFF, 32);
+                    self.push_bits(value >> 32, n - 32);
+                }
+            }
+            return;
+        }
+
+        let value: u64 = value & ((1u64 << n) - 1);
+        match self.order {
+            BitOrder::MsbFirst => {
+                self.pending = (self.pending << n) | value;
+                self.filled += n;
+                while self.filled >= 8 {
+                    self.filled -= 8;
+                    self.bytes.push((self.pending >> self.filled) as u8);
+                }
+                self.pending &= (1u64 << self.filled) - 1;
+            }
+            BitOrder::LsbFirst => {
+                self.pending |= value << self.filled;
+                self.filled += n;
+                while self.filled >= 8 {
+                    self.bytes.push(self.pending as u8);
+                    self.pending >>= 8;
+                    self.filled -= 8;
+                }
+            }
+        }
+    }
+
+    /*
+        Number of bits written after the last whole byte.
+    */
+    pub fn leftover_bits(&self) -> usize {
+        self.filled
+    }
+
+    /*
+        The whole bytes written, leftover bits are dropped.
+    */
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub struct BitReader<'a> {
+    order: BitOrder,
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8], order: BitOrder) -> BitReader<'a> {
+        BitReader {
+            order,
+            bytes,
+            position: 0,
+        }
+    }
+
+    /*
+        Reads `n` bits (at most 64) into the least significant bits of the
+        result, None when fewer than `n` bits are left.
+    */
+    pub fn read_bits(&mut self, n: usize) -> Option<u64> {
+        assert!(n <= 64, "Cannot read more than 64 bits at once");
+        if n > self.remaining_bits() {
+            return None;
+        }
+
+        // Takes as many bits as are left in the current byte at once
+        let mut value: u64 = 0;
+        let mut read: usize = 0;
+        while read < n {
+            let byte: u8 = self.bytes[self.position / 8];
+            let offset: usize = self.position % 8;
+            let length: usize = (8 - offset).min(n - read);
+            match self.order {
+                BitOrder::MsbFirst => {
+                    let bits: u8 = (byte >> (8 - offset - length)) & low_mask(length);
+                    value = (value << length) | bits as u64;
+                }
+                BitOrder::LsbFirst => {
+                    let bits: u8 = (byte >> offset) & low_mask(length);
+                    value |= (bits as u64) << read;
+                }
+            }
+            read += length;
+            self.position += length;
+        }
+        Some(value)
+    }
+
+    pub fn remaining_bits(&self) -> usize {
+        self.bytes.len() * 8 - self.position
+    }
+}
+
+fn low_mask(length: usize) -> u8 {
+    ((1u16 << length) - 1) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bits::{BitOrder, BitReader, BitWriter};
+
+    #[test]
+    fn msb_first_test() {
+        let mut writer = BitWriter::new(BitOrder::MsbFirst);
+        writer.push_bits(0b101, 3);
+        writer.push_bits(0b11001, 5);
+        writer.push_bits(0b0111, 4);
+        assert_eq!(4, writer.leftover_bits());
+        assert_eq!(vec![0b10111001], writer.into_bytes());
+
+        let mut reader = BitReader::new(&[0b10111001, 0b01110000], BitOrder::MsbFirst);
+        assert_eq!(Some(0b101), reader.read_bits(3));
+        assert_eq!(Some(0b11001), reader.read_bits(5));
+        assert_eq!(Some(0b0111), reader.read_bits(4));
+        assert_eq!(4, reader.remaining_bits());
+        assert_eq!(None, reader.read_bits(5));
+    }
+
+    #[test]
+    fn lsb_first_test() {
+        let mut writer = BitWriter::new(BitOrder::LsbFirst);
+        writer.push_bits(0b101, 3);
+        writer.push_bits(0b11001, 5);
+        writer.push_bits(0x1234, 16);
+        assert_eq!(vec![0b11001101, 0x34, 0x12], writer.into_bytes());
+
+        let mut reader = BitReader::new(&[0b11001101, 0x34, 0x12], BitOrder::LsbFirst);
+        assert_eq!(Some(0b101), reader.read_bits(3));
+        assert_eq!(Some(0b11001), reader.read_bits(5));
+        assert_eq!(Some(0x1234), reader.read_bits(16));
+        assert_eq!(Some(0), reader.read_bits(0));
+    }
+
+    #[test]
+    fn wide_round_trip_test() {
+        // Pushes wider than 56 bits are split, they must still read back whole
+        let values: [(u64, usize); 4] = [
+            (0b101, 3),
+            (0x0123_4567_89AB_CDEF, 64),
+            (0x0FED_CBA9_8765_4321, 60),
+            (0b1, 1),
+        ];
+        for order in [BitOrder::MsbFirst, BitOrder::LsbFirst] {
+            let mut writer = BitWriter::new(order);
+            for &(value, n) in values.iter() {
+                writer.push_bits(value, n);
+            }
+            assert_eq!(0, writer.leftover_bits());
+            let bytes: Vec<u8> = writer.into_bytes();
+            assert_eq!(16, bytes.len());
+
+            let mut reader = BitReader::new(&bytes, order);
+            for &(value, n) in values.iter() {
+                assert_eq!(Some(value), reader.read_bits(n));
+            }
+            assert_eq!(0, reader.remaining_bits());
+        }
+
+        let mut writer = BitWriter::new(BitOrder::MsbFirst);
+        writer.push_bits(0x0123_4567_89AB_CDEF, 64);
+        assert_eq!(
+            0x0123_4567_89AB_CDEFu64.to_be_bytes().to_vec(),
+            writer.into_bytes()
+        );
+        let mut writer = BitWriter::new(BitOrder::LsbFirst);
+        writer.push_bits(0x0123_4567_89AB_CDEF, 64);
+        assert_eq!(
+            0x0123_4567_89AB_CDEFu64.to_le_bytes().to_vec(),
+            writer.into_bytes()
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::helpers;
+use crate::layer_two::{self, ParityScheme};
+use crate::packets::{self, StreamOptions};
+use crate::rng::Rng;
+use crate::{layer_five, layer_one, layer_six, xor};
+
+/*
+    Builds an onion around a core: every layer, from the innermost one,
+    encodes the content of the layer inside it and writes it as the ASCII85
+    payload of a document with the instructions of the original onion.
+    Everything random comes from a seeded generator, so a seed always gives
+    the same onion.
+*/
+pub struct OnionBuilder {
+    rng: Rng,
+}
+
+const PAYLOAD_HEADER: &[u8] = b"==[ Payload ]===============================================";
+const XOR_KEY_LENGTH: usize = 32;
+// About one byte with an incorrect parity bit for this many correct ones
+const PARITY_NOISE: usize = 64;
+
+// The instructions of the original onion, everything before its payload headers
+const INSTRUCTIONS: [&str; 7] = [
+    include_str!("instructions/layer_0.txt"),
+    include_str!("instructions/layer_1.txt"),
+    include_str!("instructions/layer_2.txt"),
+    include_str!("instructions/layer_3.txt"),
+    include_str!("instructions/layer_4.txt"),
+    include_str!("instructions/layer_5.txt"),
+    include_str!("instructions/layer_6.txt"),
+];
+
+impl OnionBuilder {
+    pub fn new(seed: u64) -> OnionBuilder {
+        OnionBuilder {
+            rng: Rng::new(seed),
+        }
+    }
+
+    /*
+        The content of the outermost layer of an onion that peels to `core`.
+    */
+    pub fn build(&mut self, core: &[u8]) -> Vec<u8> {
+        let mut content: Vec<u8> = core.to_vec();
+        for index in (0..INSTRUCTIONS.len()).rev() {
+            let payload: Vec<u8> = self.encode_layer(index, &content);
+            content = document(index, &payload);
+        }
+        content
+    }
+
+    /*
+        The payload of layer `index` that peels to `content`.
+    */
+    fn encode_layer(&mut self, index: usize, content: &[u8]) -> Vec<u8> {
+        match index {
+            1 => layer_one::encode(content),
+            2 => self.encode_parity(content),
+            3 => {
+                let key: Vec<u8> = self.rng.bytes(XOR_KEY_LENGTH);
+                xor::apply_key(content, &key)
+            }
+            4 => packets::generate(content, &StreamOptions::default(), &mut self.rng),
+            5 => layer_five::encode(content, &mut self.rng),
+            6 => layer_six::encode(content, &mut self.rng),
+            _ => content.to_vec(),
+        }
+    }
+
+    /*
+        Encodes with the parity scheme of the layer, and inserts random bytes
+        with an incorrect parity bit.
+    */
+    fn encode_parity(&mut self, content: &[u8]) -> Vec<u8> {
+        let scheme: ParityScheme = ParityScheme::default();
+        let encoded: Vec<u8> = layer_two::encode(content, scheme);
+
+        let mut payload: Vec<u8> = Vec::with_capacity(encoded.len() + encoded.len() / PARITY_NOISE);
+        for byte in encoded {
+            if self.rng.below(PARITY_NOISE) == 0 {
+                payload.push(scheme.encode_byte(self.rng.next_u8()) ^ 0x01);
+            }
+            payload.push(byte);
+        }
+        payload
+    }
+}
+
+/*
+    The document of layer `index`: its instructions, the payload header and
+    the ASCII85 payload, laid out like the original onion.
+*/
+fn document(index: usize, payload: &[u8]) -> Vec<u8> {
+    let encoded: Vec<u8> = helpers::encode(payload);
+    let mut document: Vec<u8> = Vec::with_capacity(INSTRUCTIONS[index].len() + encoded.len() + 64);
+    document.extend_from_slice(INSTRUCTIONS[index].as_bytes());
+    document.extend_from_slice(PAYLOAD_HEADER);
+    document.extend_from_slice(b"\n\n");
+    document.extend_from_slice(&encoded);
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::OnionBuilder;
+    use crate::peel_all;
+
+    #[test]
+    fn build_test() {
+        let core: &[u8] = b"==[ The Core ]==============================================\n\n\
+            A generated onion, peeled back to the message it was built around.\n";
+        let onion: Vec<u8> = OnionBuilder::new(7).build(core);
+        assert!(onion.ends_with(b"~>"));
+        assert_eq!(onion, OnionBuilder::new(7).build(core));
+        assert_ne!(onion, OnionBuilder::new(8).build(core));
+
+        let outputs = peel_all(&onion).unwrap();
+        assert_eq!(7, outputs.len());
+        assert_eq!(core.to_vec(), outputs[6].peeled);
+    }
+}
@@ -0,0 +1,354 @@
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::error::{OnionError, Result};
+use crate::xor;
+
+const CONTEXT: usize = 12;
+const MAX_ALIGNED_LINES: usize = 24;
+const HELP: &str = "\
+Commands:
+  crib <offset> <text>   Guess that <text> is the plaintext at <offset>,
+                         \\n, \\t, \\\\ and \\xNN escapes are supported
+  undo                   Undo the last guess
+  key                    Show the partial key, ?? are unknown bytes
+  show <offset> [length] Show the decrypted text at <offset>
+  save <file>            Save the key to a file layer_three can load
+  help                   Show this help
+  quit                   Leave the session
+";
+
+/*
+    Crib dragging on a repeating XOR ciphertext: every guess of plaintext at
+    an offset reveals the key bytes under it, which decrypt the bytes at the
+    same position modulo the key length everywhere else in the ciphertext.
+*/
+pub struct CribSession {
+    ciphertext: Vec<u8>,
+    key: Vec<Option<u8>>,
+    history: Vec<Vec<Option<u8>>>,
+    highlight: bool,
+}
+
+impl CribSession {
+    /*
+        With `highlight`, guessed bytes are shown in reverse video, otherwise
+        they are surrounded by square brackets. The key length must not be 0.
+    */
+    pub fn new(ciphertext: Vec<u8>, key_length: usize, highlight: bool) -> Result<CribSession> {
+        if key_length == 0 {
+            return Err(OnionError::InvalidKeyLength { length: key_length });
+        }
+        Ok(CribSession {
+            ciphertext,
+            key: vec![None; key_length],
+            history: Vec::new(),
+            highlight,
+        })
+    }
+
+    pub fn partial_key(&self) -> &[Option<u8>] {
+        &self.key
+    }
+
+    /*
+        The key, once every one of its bytes is known.
+    */
+    pub fn key(&self) -> Option<Vec<u8>> {
+        self.key.iter().copied().collect()
+    }
+
+    pub fn place(&mut self, offset: usize, crib: &[u8]) -> Result<()> {
+        if crib.is_empty()
+            || offset > self.ciphertext.len()
+            || crib.len() > self.ciphertext.len() - offset
+        {
+            return Err(OnionError::CribOutOfRange {
+                offset,
+                length: crib.len(),
+                size: self.ciphertext.len(),
+            });
+        }
+
+        let mut key: Vec<Option<u8>> = self.key.clone();
+        let mut placed: Vec<Option<u8>> = vec![None; key.len()];
+        for (i, plain) in crib.iter().enumerate() {
+            let position: usize = offset + i;
+            let column: usize = position % key.len();
+            let key_byte: u8 = self.ciphertext[position] ^ plain;
+            if placed[column].is_some_and(|placed_byte| placed_byte != key_byte) {
+                return Err(OnionError::CribConflict { position: column });
+            }
+            placed[column] = Some(key_byte);
+            key[column] = Some(key_byte);
+        }
+
+        self.history.push(std::mem::replace(&mut self.key, key));
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.history.pop() {
+            Some(key) => {
+                self.key = key;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn render_key(&self) -> String {
+        self.key
+            .iter()
+            .map(|byte| match byte {
+                Some(byte) => format!("{:02x}", byte),
+                None => "??".to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /*
+        Decrypted text from `start` to `end` with the current key, the bytes
+        from `highlight_start` to `highlight_end` are highlighted.
+    */
+    fn render(
+        &self,
+        start: usize,
+        end: usize,
+        highlight_start: usize,
+        highlight_end: usize,
+    ) -> String {
+        let (open, close) = if self.highlight {
+            ("\x1b[7m", "\x1b[0m")
+        } else {
+            ("[", "]")
+        };
+
+        let mut line: String = String::new();
+        for position in start..end.min(self.ciphertext.len()) {
+            if position == highlight_start {
+                line.push_str(open);
+            }
+            line.push(self.display_char(position));
+            if position + 1 == highlight_end {
+                line.push_str(close);
+            }
+        }
+        line
+    }
+
+    fn display_char(&self, position: usize) -> char {
+        match self.key[position % self.key.len()] {
+            None => '·',
+            Some(key_byte) => match self.ciphertext[position] ^ key_byte {
+                b'\n' => '↵',
+                byte @ 0x20..=0x7E => byte as char,
+                _ => '¤',
+            },
+        }
+    }
+
+    /*
+        One line per position aligned with `offset` modulo the key length,
+        showing the `length` bytes decrypted there in their context. The line
+        of `offset` itself is marked with a '*'.
+    */
+    pub fn render_aligned(&self, offset: usize, length: usize) -> Vec<String> {
+        (offset % self.key.len()..self.ciphertext.len())
+            .step_by(self.key.len())
+            .take(MAX_ALIGNED_LINES)
+            .map(|position| {
+                let marker: char = if position == offset { '*' } else { ' ' };
+                let text: String = self.render(
+                    position.saturating_sub(CONTEXT),
+                    position + length + CONTEXT,
+                    position,
+                    position + length,
+                );
+                format!("{}{:>8}  {}", marker, position, text)
+            })
+            .collect()
+    }
+
+    pub fn render_text(&self, offset: usize, length: usize) -> Result<String> {
+        if offset >= self.ciphertext.len() {
+            return Err(OnionError::OffsetOutOfRange {
+                offset,
+                size: self.ciphertext.len(),
+            });
+        }
+        let end: usize = offset.saturating_add(length);
+        Ok(self.render(offset, end, usize::MAX, usize::MAX))
+    }
+}
+
+/*
+    Runs the interactive session, reading commands from `input` until it
+    ends or 'quit' is entered.
+*/
+pub fn run(session: &mut CribSession, input: impl BufRead, output: &mut impl Write) -> Result<()> {
+    writeln!(output, "{}", HELP)?;
+    prompt(output)?;
+    for line in input.lines() {
+        let line: String = line?;
+        let (command, arguments) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+        match command.trim() {
+            "" => {}
+            "quit" | "exit" => break,
+            "help" => writeln!(output, "{}", HELP)?,
+            "key" => writeln!(output, "{}", session.render_key())?,
+            "undo" => {
+                if session.undo() {
+                    writeln!(output, "{}", session.render_key())?;
+                } else {
+                    writeln!(output, "Nothing to undo")?;
+                }
+            }
+            "crib" => match parse_crib(arguments) {
+                Some((offset, crib)) => match session.place(offset, &crib) {
+                    Ok(()) => {
+                        writeln!(output, "{}", session.render_key())?;
+                        for aligned in session.render_aligned(offset, crib.len()) {
+                            writeln!(output, "{}", aligned)?;
+                        }
+                    }
+                    Err(error) => writeln!(output, "{}", error)?,
+                },
+                None => writeln!(output, "Usage: crib <offset> <text>")?,
+            },
+            "show" => {
+                let mut numbers = arguments.split_whitespace().map(str::parse::<usize>);
+                let text = match (numbers.next(), numbers.next()) {
+                    (Some(Ok(offset)), None) => session.render_text(offset, 64),
+                    (Some(Ok(offset)), Some(Ok(length))) => session.render_text(offset, length),
+                    _ => Ok("Usage: show <offset> [length]".to_string()),
+                };
+                match text {
+                    Ok(text) => writeln!(output, "{}", text)?,
+                    Err(error) => writeln!(output, "{}", error)?,
+                }
+            }
+            "save" => match (session.key(), arguments.trim()) {
+                (_, "") => writeln!(output, "Usage: save <file>")?,
+                (None, _) => writeln!(output, "The key is not complete yet")?,
+                (Some(key), path) => {
+                    xor::save_key(Path::new(path), &key)?;
+                    writeln!(output, "Key saved to {}", path)?;
+                }
+            },
+            _ => writeln!(output, "Unknown command '{}', try 'help'", command)?,
+        }
+        prompt(output)?;
+    }
+    Ok(())
+}
+
+fn prompt(output: &mut impl Write) -> Result<()> {
+    write!(output, "crib> ")?;
+    output.flush()?;
+    Ok(())
+}
+
+/*
+    Parses '<offset> <text>', the text is everything after the single space
+    that follows the offset.
+*/
+fn parse_crib(arguments: &str) -> Option<(usize, Vec<u8>)> {
+    let (offset, text) = arguments.split_once(' ')?;
+    let offset: usize = offset.parse().ok()?;
+    let crib: Vec<u8> = unescape(text)?;
+    Some((offset, crib))
+}
+
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    let mut iter = text.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match iter.next()? {
+            b'n' => bytes.push(b'\n'),
+            b't' => bytes.push(b'\t'),
+            b'\\' => bytes.push(b'\\'),
+            b'x' => {
+                let hex: [u8; 2] = [iter.next()?, iter.next()?];
+                let hex: &str = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crib::{run, unescape, CribSession};
+    use crate::xor::apply_key;
+
+    #[test]
+    fn crib_session_test() {
+        let key: Vec<u8> = vec![0x11, 0x22, 0x33, 0x44];
+        let ciphertext: Vec<u8> = apply_key(b"==[ Layer 4/6: Network ]==\n\n", &key);
+        let mut session = CribSession::new(ciphertext, 4, false).unwrap();
+
+        session.place(0, b"==[").unwrap();
+        assert_eq!("11 22 33 ??", session.render_key());
+        assert_eq!(None, session.key());
+        assert_eq!("==[·La", session.render_text(0, 6).unwrap());
+        assert_eq!(
+            vec![
+                "*       0  [==[]·Lay·r 4·6: ",
+                "        4  ==[·[Lay]·r 4·6: ·etw"
+            ],
+            session.render_aligned(0, 3)[..2].to_vec()
+        );
+
+        session.place(7, b"e").unwrap();
+        assert_eq!(Some(key), session.key());
+        assert_eq!("Network ]==↵↵", session.render_text(15, 20).unwrap());
+
+        assert!(session.undo());
+        assert_eq!("11 22 33 ??", session.render_key());
+        assert!(session.undo());
+        assert!(!session.undo());
+
+        // Does not fit, or needs two different bytes for the same key byte
+        assert!(session.place(27, b"==").is_err());
+        assert!(session.place(0, b"==[ ====").is_err());
+        assert!(session.place(usize::MAX, b"x").is_err());
+        assert!(session.render_text(usize::MAX, 64).is_err());
+        assert!(CribSession::new(vec![0x42], 0, false).is_err());
+    }
+
+    #[test]
+    fn run_test() {
+        let ciphertext: Vec<u8> = apply_key(b"Hello, world!", &[0x42, 0x24]);
+        let mut session = CribSession::new(ciphertext, 2, false).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+
+        run(
+            &mut session,
+            &b"crib 0 He\nkey\nundo\nfoo\nshow 18446744073709551615\n"[..],
+            &mut output,
+        )
+        .unwrap();
+        let output: String = String::from_utf8(output).unwrap();
+        assert!(output.contains("*       0  [He]llo, world!"));
+        assert!(output.contains("42 24"));
+        assert!(output.contains("?? ??"));
+        assert!(output.contains("Unknown command 'foo'"));
+        assert!(output
+            .contains("The offset 18446744073709551615 must be within the 13 bytes of ciphertext"));
+    }
+
+    #[test]
+    fn unescape_test() {
+        assert_eq!(Some(b"a\n\t\\\xff".to_vec()), unescape("a\\n\\t\\\\\\xff"));
+        assert_eq!(None, unescape("\\q"));
+        assert_eq!(None, unescape("\\x4"));
+    }
+}
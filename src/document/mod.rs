@@ -0,0 +1,166 @@
+use crate::encoding::{find, BTOA_BEGIN, BTOA_END};
+use crate::error::{OnionError, Result};
+
+const TITLE_PREFIX: &[u8] = b"==[ Layer ";
+const PAYLOAD_HEADER: &[u8] = b"==[ Payload ]";
+const PAYLOAD_START: &[u8] = b"<~";
+const PAYLOAD_END: &[u8] = b"~>";
+
+/*
+    A layer of the onion split into its parts:
+
+        ==[ Layer N/6: Name ]======   <-- title
+        ...                           <-- instructions, up to the payload header
+        ==[ Payload ]==============
+        <~ ... ~>                     <-- ASCII85 payload, delimiters included
+
+    Anything after the '~>' terminator is not part of the layer.
+*/
+pub struct LayerDocument<'a> {
+    pub title: Option<LayerTitle>,
+    pub instructions: &'a [u8],
+    pub payload: &'a [u8],
+    // Where the payload starts in the content of the layer
+    pub payload_offset: usize,
+}
+
+/*
+    The title line of a layer, e.g. '==[ Layer 4/6: Network Traffic ]'.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerTitle {
+    pub index: usize,
+    pub count: usize,
+    pub name: String,
+}
+
+impl<'a> LayerDocument<'a> {
+    /*
+        Parses the content of layer `layer`, the payload is the block that
+        starts on the first line after the '==[ Payload ]' header that is not
+        empty: a '<~' to '~>' block, an 'xbtoa Begin' to 'xbtoa End' block,
+        or else the lines up to the next empty line. A title is optional, but
+        when there is one it must be the title of layer `layer`.
+    */
+    pub fn parse(layer: usize, content: &'a [u8]) -> Result<LayerDocument<'a>> {
+        let header: usize =
+            find(content, PAYLOAD_HEADER, 0).ok_or(OnionError::MissingPayloadHeader { layer })?;
+        let header_end: usize = find(content, b"\n", header).map_or(content.len(), |i| i + 1);
+        let start: usize = (header_end..content.len())
+            .find(|&i| !content[i].is_ascii_whitespace())
+            .ok_or(OnionError::MissingPayloadMarker { layer })?;
+        let terminator_error = OnionError::MissingPayloadTerminator {
+            layer,
+            offset: start,
+        };
+
+        let block: &[u8] = &content[start..];
+        let end: usize = if block.starts_with(PAYLOAD_START) {
+            find(content, PAYLOAD_END, start + PAYLOAD_START.len()).ok_or(terminator_error)?
+                + PAYLOAD_END.len()
+        } else if block.starts_with(BTOA_BEGIN) {
+            let trailer: usize = find(content, BTOA_END, start).ok_or(terminator_error)?;
+            find(content, b"\n", trailer).unwrap_or(content.len())
+        } else {
+            let paragraph_end: usize = find(content, b"\n\n", start).unwrap_or(content.len());
+            start + content[start..paragraph_end].trim_ascii_end().len()
+        };
+
+        let instructions: &[u8] = &content[..header];
+        let title: Option<LayerTitle> = parse_title(instructions);
+        if let Some(title) = &title {
+            if title.index != layer {
+                return Err(OnionError::LayerMismatch {
+                    layer,
+                    title: title.index,
+                });
+            }
+        }
+
+        Ok(LayerDocument {
+            title,
+            instructions,
+            payload: &content[start..end],
+            payload_offset: start,
+        })
+    }
+}
+
+/*
+    Finds the first line that looks like '==[ Layer N/M: Name ]'.
+*/
+fn parse_title(instructions: &[u8]) -> Option<LayerTitle> {
+    instructions
+        .split(|&byte| byte == b'\n')
+        .filter(|line| line.starts_with(TITLE_PREFIX))
+        .find_map(|line| {
+            let line: &str = std::str::from_utf8(&line[TITLE_PREFIX.len()..]).ok()?;
+            let (numbers, rest) = line.split_once(": ")?;
+            let (index, count) = numbers.split_once('/')?;
+            let name: &str = &rest[..rest.find(" ]")?];
+            Some(LayerTitle {
+                index: index.parse().ok()?,
+                count: count.parse().ok()?,
+                name: name.to_string(),
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::{LayerDocument, LayerTitle};
+    use crate::error::OnionError;
+
+    #[test]
+    fn parse_test() {
+        let content: &[u8] = b"==[ Layer 2/6: Parity Bit ]======\n\nThe <~ marker is \
+            prose here.\n\n==[ Payload ]=====\n\n<~9jqo^\nF*2M7/c~>\n\ntrailing text";
+        let document = LayerDocument::parse(2, content).unwrap();
+
+        assert_eq!(
+            Some(LayerTitle {
+                index: 2,
+                count: 6,
+                name: "Parity Bit".to_string(),
+            }),
+            document.title
+        );
+        assert!(document.instructions.ends_with(b"prose here.\n\n"));
+        assert_eq!(b"<~9jqo^\nF*2M7/c~>", document.payload);
+
+        let content: &[u8] = b"==[ Payload ]===\n\nTWFu\nTWFu  \n\ntrailing text";
+        assert_eq!(
+            b"TWFu\nTWFu",
+            LayerDocument::parse(2, content).unwrap().payload
+        );
+
+        let content: &[u8] = b"==[ Payload ]\nxbtoa Begin\nz\nxbtoa End N 4\n\ntrailing text";
+        assert_eq!(
+            b"xbtoa Begin\nz\nxbtoa End N 4",
+            LayerDocument::parse(2, content).unwrap().payload
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        match LayerDocument::parse(1, b"==[ Layer 1/6: X ]\n<~abc~>") {
+            Err(OnionError::MissingPayloadHeader { layer: 1 }) => {}
+            _ => panic!("Expected a missing payload header"),
+        }
+        match LayerDocument::parse(1, b"==[ Payload ]\n") {
+            Err(OnionError::MissingPayloadMarker { layer: 1 }) => {}
+            _ => panic!("Expected a missing payload marker"),
+        }
+        match LayerDocument::parse(1, b"==[ Payload ]\n<~abc") {
+            Err(OnionError::MissingPayloadTerminator {
+                layer: 1,
+                offset: 14,
+            }) => {}
+            _ => panic!("Expected a missing payload terminator"),
+        }
+        match LayerDocument::parse(3, b"==[ Layer 1/6: X ]\n==[ Payload ]\n<~abc~>") {
+            Err(OnionError::LayerMismatch { layer: 3, title: 1 }) => {}
+            _ => panic!("Expected a layer mismatch"),
+        }
+    }
+}
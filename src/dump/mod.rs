@@ -0,0 +1,103 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::error::Result;
+
+const MANIFEST_FILE: &str = "manifest.json";
+const OUTPUT_FILE: &str = "output.txt";
+
+/*
+    Writes every layer to a directory while the onion is being peeled:
+    'layer_N.txt' holds the instructions text and 'layer_N.payload.bin' the
+    ASCII85 decoded payload of layer N. The manifest is rewritten after every
+    file so it is complete even when a layer cannot be peeled.
+*/
+pub struct Dumper {
+    dir: PathBuf,
+    layers: Vec<Value>,
+    output: Option<Value>,
+}
+
+impl Dumper {
+    pub fn new(dir: &Path) -> Result<Dumper> {
+        fs::create_dir_all(dir)?;
+        Ok(Dumper {
+            dir: dir.to_path_buf(),
+            layers: Vec::new(),
+            output: None,
+        })
+    }
+
+    pub fn dump_layer(
+        &mut self,
+        index: usize,
+        name: &str,
+        instructions: &[u8],
+        payload: &[u8],
+    ) -> Result<()> {
+        let instructions: Value = self.write_file(&format!("layer_{}.txt", index), instructions)?;
+        let payload: Value = self.write_file(&format!("layer_{}.payload.bin", index), payload)?;
+
+        self.layers.push(json!({
+            "index": index,
+            "name": name,
+            "instructions": instructions,
+            "payload": payload,
+        }));
+        self.write_manifest()
+    }
+
+    /*
+        Writes the content peeled from the last layer, which is the next
+        layer or the core of the onion.
+    */
+    pub fn dump_output(&mut self, peeled: &[u8]) -> Result<()> {
+        self.output = Some(self.write_file(OUTPUT_FILE, peeled)?);
+        self.write_manifest()
+    }
+
+    fn write_file(&self, file_name: &str, content: &[u8]) -> Result<Value> {
+        fs::write(self.dir.join(file_name), content)?;
+        Ok(json!({
+            "file": file_name,
+            "size": content.len(),
+            "sha256": sha256_hex(content),
+        }))
+    }
+
+    fn write_manifest(&self) -> Result<()> {
+        let manifest: Value = json!({
+            "layers": self.layers,
+            "output": self.output,
+        });
+        fs::write(
+            self.dir.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(&manifest).map_err(io::Error::from)?,
+        )?;
+        Ok(())
+    }
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::sha256_hex;
+
+    #[test]
+    fn sha256_test() {
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            sha256_hex(b"abc")
+        );
+    }
+}
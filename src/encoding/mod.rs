@@ -0,0 +1,747 @@
+use thiserror::Error;
+
+use crate::ascii85::{self, base85_digits, EncodeOptions, ALPHABET as ASCII85_ALPHABET};
+use crate::bits::{BitOrder, BitReader, BitWriter};
+
+/*
+    A way of writing binary data as text, as the payload of a layer.
+    Decoders ignore whitespace, encoders wrap their output in lines.
+*/
+pub trait TextEncoding {
+    fn name(&self) -> &'static str;
+    /*
+        Whether `block` looks like this encoding: it has its delimiters, or
+        every character is in its alphabet. It may still fail to decode.
+    */
+    fn accepts(&self, block: &[u8]) -> bool;
+    fn decode(&self, block: &[u8]) -> Result<Vec<u8>, DecodeError>;
+    fn encode(&self, data: &[u8]) -> Vec<u8>;
+}
+
+/*
+    Every encoding, in the order they are tried by `detect`: the ones with
+    delimiters first, then from the smallest alphabet to the largest.
+*/
+pub fn registry() -> Vec<Box<dyn TextEncoding>> {
+    vec![
+        Box::new(AdobeAscii85 { delimited: true }),
+        Box::new(Btoa),
+        Box::new(Radix::HEX),
+        Box::new(Radix::BASE32),
+        Box::new(Radix::BASE64),
+        Box::new(Radix::BASE64_URL),
+        Box::new(AdobeAscii85 { delimited: false }),
+        Box::new(Base85::Z85),
+        Box::new(Base85::RFC_1924),
+    ]
+}
+
+pub struct Detected {
+    pub encoding: &'static str,
+    pub decoded: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DetectError {
+    // No encoding accepts the block
+    Unknown,
+    // The error of the first encoding that accepts the block
+    Invalid {
+        encoding: &'static str,
+        error: DecodeError,
+    },
+}
+
+/*
+    Decodes `block` with the first encoding of the registry that accepts it
+    and decodes it without error.
+*/
+pub fn detect(block: &[u8]) -> Result<Detected, DetectError> {
+    let mut first_error: Option<DetectError> = None;
+    for encoding in registry() {
+        if !encoding.accepts(block) {
+            continue;
+        }
+        match encoding.decode(block) {
+            Ok(decoded) => {
+                return Ok(Detected {
+                    encoding: encoding.name(),
+                    decoded,
+                })
+            }
+            Err(error) => {
+                first_error.get_or_insert(DetectError::Invalid {
+                    encoding: encoding.name(),
+                    error,
+                });
+            }
+        }
+    }
+    Err(first_error.unwrap_or(DetectError::Unknown))
+}
+
+/*
+    Where a character is in the encoded data: the byte offset from the
+    start, and the line and column, both counted from 1.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn locate(data: &[u8], offset: usize) -> Position {
+        let before: &[u8] = &data[..offset.min(data.len())];
+        let line_start: usize = before
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |newline| newline + 1);
+        Position {
+            offset,
+            line: before.iter().filter(|&&byte| byte == b'\n').count() + 1,
+            column: offset - line_start + 1,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("{kind} at byte {}, line {}, column {}", .position.offset, .position.line, .position.column)]
+pub struct DecodeError {
+    pub kind: ErrorKind,
+    pub position: Position,
+}
+
+impl DecodeError {
+    fn at(kind: ErrorKind, block: &[u8], offset: usize) -> DecodeError {
+        DecodeError {
+            kind,
+            position: Position::locate(block, offset),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ErrorKind {
+    #[error("invalid character {0:#04x}")]
+    InvalidCharacter(u8),
+    #[error("'z' inside a group")]
+    MisplacedZ,
+    #[error("group larger than 32 bits")]
+    Overflow,
+    #[error("incomplete final group")]
+    TruncatedGroup,
+    #[error("missing terminator")]
+    MissingTerminator,
+    #[error("data after the end of the encoded data")]
+    DataAfterTerminator,
+    #[error("malformed trailer")]
+    MalformedTrailer,
+    #[error("length or checksum mismatch")]
+    ChecksumMismatch,
+    #[error("wrong number of padding characters")]
+    InvalidPadding,
+    #[error("non zero bits after the last byte")]
+    TrailingBits,
+}
+
+/*
+    The encoding of the onion, see the `ascii85` module. Without
+    delimiters, it accepts blocks made of its alphabet and 'z' only.
+*/
+struct AdobeAscii85 {
+    delimited: bool,
+}
+
+impl TextEncoding for AdobeAscii85 {
+    fn name(&self) -> &'static str {
+        "Adobe ASCII85"
+    }
+
+    fn accepts(&self, block: &[u8]) -> bool {
+        if self.delimited {
+            block.starts_with(b"<~")
+        } else {
+            in_alphabet(block, |byte| (b'!'..=b'u').contains(&byte) || byte == b'z')
+        }
+    }
+
+    fn decode(&self, block: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        ascii85::decode(block)
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let options: EncodeOptions = EncodeOptions {
+            delimiters: self.delimited,
+            ..EncodeOptions::default()
+        };
+        ascii85::encode(data, &options)
+    }
+}
+
+/*
+    Encodings where every character carries the same number of bits: hex,
+    base32 and base64. The final character is padded with '0' bits, and the
+    output with '=' up to a whole number of bytes when `padded`.
+*/
+struct Radix {
+    name: &'static str,
+    alphabet: &'static [u8],
+    bits: usize,
+    padded: bool,
+    case_insensitive: bool,
+}
+
+impl Radix {
+    const HEX: Radix = Radix {
+        name: "hex",
+        alphabet: b"0123456789abcdef",
+        bits: 4,
+        padded: false,
+        case_insensitive: true,
+    };
+    const BASE32: Radix = Radix {
+        name: "base32",
+        alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567",
+        bits: 5,
+        padded: true,
+        case_insensitive: false,
+    };
+    const BASE64: Radix = Radix {
+        name: "base64",
+        alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+        bits: 6,
+        padded: true,
+        case_insensitive: false,
+    };
+    const BASE64_URL: Radix = Radix {
+        name: "base64url",
+        alphabet: b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+        bits: 6,
+        padded: false,
+        case_insensitive: false,
+    };
+
+    fn table(&self) -> [Option<u8>; 256] {
+        let mut table: [Option<u8>; 256] = [None; 256];
+        for (value, character) in self.alphabet.iter().enumerate() {
+            table[*character as usize] = Some(value as u8);
+            if self.case_insensitive {
+                table[character.to_ascii_uppercase() as usize] = Some(value as u8);
+            }
+        }
+        table
+    }
+
+    // Padding is accepted when decoding any of the encodings that may use it
+    fn may_pad(&self) -> bool {
+        self.bits != 4
+    }
+
+    // Characters for a whole number of bytes: 4 for base64, 8 for base32
+    fn group(&self) -> usize {
+        (1..=8)
+            .find(|n| (n * self.bits).is_multiple_of(8))
+            .unwrap_or(1)
+    }
+}
+
+impl TextEncoding for Radix {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn accepts(&self, block: &[u8]) -> bool {
+        let table: [Option<u8>; 256] = self.table();
+        in_alphabet(block, |byte| {
+            table[byte as usize].is_some() || (byte == b'=' && self.may_pad())
+        })
+    }
+
+    /*
+        Only the canonical encoding is accepted: the padding, required by
+        base32 and base64, completes the last group, and the bits left after
+        the last byte are 0.
+    */
+    fn decode(&self, block: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let table: [Option<u8>; 256] = self.table();
+        let mut writer: BitWriter = BitWriter::new(BitOrder::MsbFirst);
+        let mut characters: usize = 0;
+        let mut padding: usize = 0;
+        let mut padding_offset: usize = block.len();
+        let mut last_value: u8 = 0;
+        let mut last_offset: usize = 0;
+
+        for (offset, byte) in block.iter().enumerate() {
+            if byte.is_ascii_whitespace() {
+                continue;
+            }
+            if *byte == b'=' && self.may_pad() {
+                padding_offset = padding_offset.min(offset);
+                padding += 1;
+                continue;
+            }
+            let value: u8 = table[*byte as usize].ok_or_else(|| {
+                DecodeError::at(ErrorKind::InvalidCharacter(*byte), block, offset)
+            })?;
+            if padding > 0 {
+                return Err(DecodeError::at(
+                    ErrorKind::DataAfterTerminator,
+                    block,
+                    offset,
+                ));
+            }
+            writer.push_bits(value as u64, self.bits);
+            characters += 1;
+            last_value = value;
+            last_offset = offset;
+        }
+
+        // The last character must complete a byte, with 0 in its extra bits
+        let leftover_bits: usize = writer.leftover_bits();
+        if leftover_bits >= self.bits {
+            return Err(DecodeError::at(
+                ErrorKind::TruncatedGroup,
+                block,
+                last_offset,
+            ));
+        }
+        if last_value & ((1u8 << leftover_bits) - 1) != 0 {
+            return Err(DecodeError::at(ErrorKind::TrailingBits, block, last_offset));
+        }
+
+        let group: usize = self.group();
+        let expected_padding: usize = (group - characters % group) % group;
+        if (self.padded || padding > 0) && padding != expected_padding {
+            return Err(DecodeError::at(
+                ErrorKind::InvalidPadding,
+                block,
+                padding_offset.min(block.len().saturating_sub(1)),
+            ));
+        }
+        Ok(writer.into_bytes())
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut reader: BitReader = BitReader::new(data, BitOrder::MsbFirst);
+        let mut encoded: Vec<u8> = Vec::with_capacity(data.len() * 8 / self.bits + 8);
+        while reader.remaining_bits() > 0 {
+            let length: usize = self.bits.min(reader.remaining_bits());
+            let value: u64 = reader.read_bits(length).unwrap_or_default() << (self.bits - length);
+            encoded.push(self.alphabet[value as usize]);
+        }
+
+        if self.padded {
+            let group: usize = self.group();
+            while !encoded.len().is_multiple_of(group) {
+                encoded.push(b'=');
+            }
+        }
+        wrap(&encoded, LINE_WIDTH)
+    }
+}
+
+/*
+    Base 85 encodings with another alphabet than ASCII85 and no 'z': Z85
+    (ZeroMQ) and the one of RFC 1924. A final group of 1 to 3 bytes is
+    written as 2 to 4 characters like in ASCII85.
+*/
+struct Base85 {
+    name: &'static str,
+    alphabet: &'static [u8; 85],
+}
+
+impl Base85 {
+    const Z85: Base85 = Base85 {
+        name: "Z85",
+        alphabet:
+            b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#",
+    };
+    const RFC_1924: Base85 = Base85 {
+        name: "RFC 1924 base85",
+        alphabet:
+            b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~",
+    };
+
+    fn table(&self) -> [Option<u8>; 256] {
+        let mut table: [Option<u8>; 256] = [None; 256];
+        for (value, character) in self.alphabet.iter().enumerate() {
+            table[*character as usize] = Some(value as u8);
+        }
+        table
+    }
+}
+
+impl TextEncoding for Base85 {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn accepts(&self, block: &[u8]) -> bool {
+        let table: [Option<u8>; 256] = self.table();
+        in_alphabet(block, |byte| table[byte as usize].is_some())
+    }
+
+    fn decode(&self, block: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let table: [Option<u8>; 256] = self.table();
+        let mut decoded: Vec<u8> = Vec::with_capacity(block.len() / 5 * 4);
+        let mut group: u64 = 0;
+        let mut digits: usize = 0;
+        let mut group_start: usize = 0;
+
+        let mut push_group = |group: u64, length: usize, group_start: usize| {
+            if group > u32::MAX as u64 {
+                return Err(DecodeError::at(ErrorKind::Overflow, block, group_start));
+            }
+            decoded.extend_from_slice(&(group as u32).to_be_bytes()[..length]);
+            Ok(())
+        };
+
+        for (offset, byte) in block.iter().enumerate() {
+            if byte.is_ascii_whitespace() {
+                continue;
+            }
+            let value: u8 = table[*byte as usize].ok_or_else(|| {
+                DecodeError::at(ErrorKind::InvalidCharacter(*byte), block, offset)
+            })?;
+            if digits == 0 {
+                group_start = offset;
+            }
+            group = group * 85 + value as u64;
+            digits += 1;
+            if digits == 5 {
+                push_group(group, 4, group_start)?;
+                group = 0;
+                digits = 0;
+            }
+        }
+
+        match digits {
+            0 => {}
+            1 => {
+                return Err(DecodeError::at(
+                    ErrorKind::TruncatedGroup,
+                    block,
+                    group_start,
+                ))
+            }
+            _ => {
+                for _ in digits..5 {
+                    group = group * 85 + 84;
+                }
+                push_group(group, digits - 1, group_start)?;
+            }
+        }
+        Ok(decoded)
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut encoded: Vec<u8> = Vec::with_capacity(data.len() / 4 * 5 + 5);
+        for group in data.chunks(4) {
+            encoded.extend_from_slice(&base85_digits(group, self.alphabet));
+        }
+        wrap(&encoded, LINE_WIDTH)
+    }
+}
+
+/*
+    The format of the btoa tool: ASCII85 digits, 'z' for 4 zero bytes and
+    'y' for 4 spaces, between an 'xbtoa Begin' line and an 'xbtoa End' line
+    giving the length and checksums of the data. A final group of 1 to 3
+    bytes is padded with zero bytes and written in full.
+*/
+struct Btoa;
+
+pub(crate) const BTOA_BEGIN: &[u8] = b"xbtoa Begin";
+pub(crate) const BTOA_END: &[u8] = b"xbtoa End";
+const BTOA_LINE_WIDTH: usize = 78;
+
+/*
+    The checksums of the 'xbtoa End' line: the XOR of every byte, their sum
+    plus one each, and a rotating checksum.
+*/
+#[derive(Default, PartialEq)]
+struct BtoaChecksums {
+    eor: u32,
+    sum: u32,
+    rotation: u32,
+}
+
+impl BtoaChecksums {
+    fn new(data: &[u8]) -> BtoaChecksums {
+        let mut checksums: BtoaChecksums = BtoaChecksums::default();
+        for byte in data {
+            checksums.eor ^= *byte as u32;
+            checksums.sum = checksums.sum.wrapping_add(*byte as u32 + 1);
+            checksums.rotation = checksums.rotation.rotate_left(1).wrapping_add(*byte as u32);
+        }
+        checksums
+    }
+}
+
+impl TextEncoding for Btoa {
+    fn name(&self) -> &'static str {
+        "btoa"
+    }
+
+    fn accepts(&self, block: &[u8]) -> bool {
+        block.trim_ascii_start().starts_with(BTOA_BEGIN)
+    }
+
+    fn decode(&self, block: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let begin: usize = find(block, BTOA_BEGIN, 0).unwrap_or_default();
+        let body_start: usize = find(block, b"\n", begin).unwrap_or(block.len());
+        let end: usize = find(block, BTOA_END, body_start)
+            .ok_or_else(|| DecodeError::at(ErrorKind::MissingTerminator, block, block.len()))?;
+
+        let mut decoded: Vec<u8> = Vec::with_capacity((end - body_start) / 5 * 4);
+        let mut group: u64 = 0;
+        let mut digits: usize = 0;
+        let mut group_start: usize = 0;
+        for (offset, byte) in block.iter().enumerate().take(end).skip(body_start) {
+            match byte {
+                _ if byte.is_ascii_whitespace() => continue,
+                b'z' | b'y' if digits > 0 => {
+                    return Err(DecodeError::at(ErrorKind::MisplacedZ, block, offset))
+                }
+                b'z' => decoded.extend_from_slice(&[0; 4]),
+                b'y' => decoded.extend_from_slice(b"    "),
+                b'!'..=b'u' => {
+                    if digits == 0 {
+                        group_start = offset;
+                    }
+                    group = group * 85 + (byte - b'!') as u64;
+                    digits += 1;
+                    if digits == 5 {
+                        if group > u32::MAX as u64 {
+                            return Err(DecodeError::at(ErrorKind::Overflow, block, group_start));
+                        }
+                        decoded.extend_from_slice(&(group as u32).to_be_bytes());
+                        group = 0;
+                        digits = 0;
+                    }
+                }
+                _ => {
+                    return Err(DecodeError::at(
+                        ErrorKind::InvalidCharacter(*byte),
+                        block,
+                        offset,
+                    ))
+                }
+            }
+        }
+        if digits > 0 {
+            return Err(DecodeError::at(
+                ErrorKind::TruncatedGroup,
+                block,
+                group_start,
+            ));
+        }
+
+        let mismatch = || DecodeError::at(ErrorKind::ChecksumMismatch, block, end);
+        let (length, checksums): (usize, BtoaChecksums) = parse_btoa_trailer(&block[end..])
+            .ok_or_else(|| DecodeError::at(ErrorKind::MalformedTrailer, block, end))?;
+        if length > decoded.len() || decoded.len() - length >= 4 {
+            return Err(mismatch());
+        }
+        decoded.truncate(length);
+        if BtoaChecksums::new(&decoded) != checksums {
+            return Err(mismatch());
+        }
+        Ok(decoded)
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut body: Vec<u8> = Vec::with_capacity(data.len() / 4 * 5 + 5);
+        for group in data.chunks(4) {
+            let mut padded: [u8; 4] = [0; 4];
+            padded[..group.len()].copy_from_slice(group);
+            match &padded {
+                [0, 0, 0, 0] => body.push(b'z'),
+                b"    " => body.push(b'y'),
+                _ => body.extend_from_slice(&base85_digits(&padded, ASCII85_ALPHABET)),
+            }
+        }
+
+        let checksums: BtoaChecksums = BtoaChecksums::new(data);
+        let mut encoded: Vec<u8> = BTOA_BEGIN.to_vec();
+        encoded.push(b'\n');
+        encoded.extend_from_slice(&wrap(&body, BTOA_LINE_WIDTH));
+        if !body.is_empty() {
+            encoded.push(b'\n');
+        }
+        encoded.extend_from_slice(
+            format!(
+                "xbtoa End N {} {:x} E {:x} S {:x} R {:x}",
+                data.len(),
+                data.len(),
+                checksums.eor,
+                checksums.sum,
+                checksums.rotation
+            )
+            .as_bytes(),
+        );
+        encoded
+    }
+}
+
+/*
+    Parses 'xbtoa End N <length> <length in hex> E <eor> S <sum> R <rotation>'.
+*/
+fn parse_btoa_trailer(trailer: &[u8]) -> Option<(usize, BtoaChecksums)> {
+    let line: &[u8] = trailer.split(|&byte| byte == b'\n').next()?;
+    let line: &str = std::str::from_utf8(line).ok()?;
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    match fields[..] {
+        ["xbtoa", "End", "N", length, length_hex, "E", eor, "S", sum, "R", rotation] => {
+            let length: usize = length.parse().ok()?;
+            if usize::from_str_radix(length_hex, 16).ok()? != length {
+                return None;
+            }
+            Some((
+                length,
+                BtoaChecksums {
+                    eor: u32::from_str_radix(eor, 16).ok()?,
+                    sum: u32::from_str_radix(sum, 16).ok()?,
+                    rotation: u32::from_str_radix(rotation, 16).ok()?,
+                },
+            ))
+        }
+        _ => None,
+    }
+}
+
+const LINE_WIDTH: usize = 60;
+
+fn in_alphabet(block: &[u8], contains: impl Fn(u8) -> bool) -> bool {
+    block
+        .iter()
+        .all(|&byte| byte.is_ascii_whitespace() || contains(byte))
+}
+
+fn wrap(encoded: &[u8], width: usize) -> Vec<u8> {
+    encoded.chunks(width).collect::<Vec<&[u8]>>().join(&b'\n')
+}
+
+/*
+    The offset of the first `needle` in `haystack` at or after `from`.
+*/
+pub(crate) fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|position| position + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoding::{detect, registry, DetectError, ErrorKind, TextEncoding};
+
+    fn encoding(name: &str) -> Box<dyn TextEncoding> {
+        registry()
+            .into_iter()
+            .find(|encoding| encoding.name() == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn vectors_test() {
+        let vectors: Vec<(&str, &[u8], &[u8])> = vec![
+            ("hex", b"Man", b"4d616e"),
+            ("base32", b"abc", b"MFRGG==="),
+            ("base64", b"Man", b"TWFu"),
+            ("base64", &[0xFB, 0xFF], b"+/8="),
+            ("base64url", &[0xFB, 0xFF], b"-_8"),
+            (
+                "Z85",
+                &[0x86, 0x4F, 0xD2, 0x6F, 0xB5, 0x59, 0xF7, 0x5B],
+                b"HelloWorld",
+            ),
+            ("RFC 1924 base85", b"Man", b"O<`^"),
+            ("Adobe ASCII85", b"Man sure.", b"<~9jqo^F*2M7/c~>"),
+        ];
+        for (name, data, encoded) in vectors {
+            assert_eq!(encoded.to_vec(), encoding(name).encode(data), "{}", name);
+            assert_eq!(data.to_vec(), encoding(name).decode(encoded).unwrap());
+        }
+        assert_eq!(
+            b"Man".to_vec(),
+            encoding("hex").decode(b"4D 61\n6E").unwrap()
+        );
+        assert_eq!(
+            b"Man".to_vec(),
+            encoding("base64").decode(b"TW\nFu").unwrap()
+        );
+    }
+
+    #[test]
+    fn round_trip_and_detect() {
+        let data: &[u8] = b"Tom's Data Onion!\n\0\0\0\0    and \xff\xfe binary";
+        for encoding in registry() {
+            for length in 0..data.len() {
+                let encoded: Vec<u8> = encoding.encode(&data[..length]);
+                assert_eq!(
+                    data[..length].to_vec(),
+                    encoding.decode(&encoded).unwrap(),
+                    "{} of {} bytes",
+                    encoding.name(),
+                    length
+                );
+            }
+
+            let detected = detect(&encoding.encode(data)).unwrap();
+            assert_eq!(data.to_vec(), detected.decoded);
+            if encoding.name() != "Adobe ASCII85" {
+                assert_eq!(encoding.name(), detected.encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let kind = |name: &str, block: &[u8]| encoding(name).decode(block).unwrap_err().kind;
+        assert_eq!(ErrorKind::TruncatedGroup, kind("hex", b"4d6"));
+        assert_eq!(ErrorKind::TruncatedGroup, kind("base64", b"TWFuT"));
+        assert_eq!(ErrorKind::DataAfterTerminator, kind("base64", b"TQ==TQ"));
+        assert_eq!(ErrorKind::InvalidCharacter(b'1'), kind("base32", b"MFRG1"));
+        assert_eq!(ErrorKind::Overflow, kind("Z85", b"#####"));
+
+        // Only canonical padding and trailing bits, so that base64 made of the
+        // base32 alphabet is not taken for base32
+        assert_eq!(ErrorKind::InvalidPadding, kind("base32", b"IFBEG=="));
+        assert_eq!(ErrorKind::InvalidPadding, kind("base32", b"IFBEG"));
+        assert_eq!(ErrorKind::TrailingBits, kind("base32", b"IFBEH==="));
+        assert_eq!(ErrorKind::InvalidPadding, kind("base64", b"QUI"));
+        assert_eq!(ErrorKind::InvalidPadding, kind("base64", b"QUI=="));
+        assert_eq!(ErrorKind::TrailingBits, kind("base64", b"QUJ="));
+        assert_eq!(ErrorKind::InvalidPadding, kind("base64url", b"QUI=="));
+        assert_eq!(
+            b"AB".to_vec(),
+            encoding("base64url").decode(b"QUI=").unwrap()
+        );
+        let detected = detect(b"QUJD").unwrap();
+        assert_eq!(
+            ("base64", b"ABC".to_vec()),
+            (detected.encoding, detected.decoded)
+        );
+
+        let encoded: Vec<u8> = encoding("btoa").encode(b"Tom's Data Onion");
+        let mut corrupted: Vec<u8> = encoded.clone();
+        corrupted[12] = if corrupted[12] == b'!' { b'"' } else { b'!' };
+        assert_eq!(ErrorKind::ChecksumMismatch, kind("btoa", &corrupted));
+        let truncated: &[u8] = &encoded[..encoded.len() - 30];
+        assert_eq!(ErrorKind::MissingTerminator, kind("btoa", truncated));
+
+        match detect(b"<~9jqo^\n/c{~>") {
+            Err(DetectError::Invalid { encoding, error }) => {
+                assert_eq!("Adobe ASCII85", encoding);
+                assert_eq!(ErrorKind::InvalidCharacter(b'{'), error.kind);
+                assert_eq!((2, 3), (error.position.line, error.position.column));
+            }
+            _ => panic!("Expected an invalid ASCII85 payload"),
+        }
+        assert_eq!(Err(DetectError::Unknown), detect(b"\xff\x00").map(|_| ()));
+    }
+}
@@ -0,0 +1,92 @@
+use std::io;
+
+use thiserror::Error;
+
+use crate::encoding::DecodeError;
+
+pub type Result<T> = std::result::Result<T, OnionError>;
+
+/*
+    Everything that can go wrong while peeling the onion. Errors raised by a
+    layer carry the index of that layer and, where it makes sense, the byte
+    offset in the layer's payload (or output) at which the problem was found.
+*/
+#[derive(Debug, Error)]
+pub enum OnionError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Cannot read from input file {path}: {source}")]
+    ReadInput { path: String, source: io::Error },
+
+    #[error("Invalid layer range {start} to {stop}")]
+    InvalidLayerRange { start: usize, stop: usize },
+
+    #[error("Expected layer {layer} but the title of the input is layer {title}")]
+    LayerMismatch { layer: usize, title: usize },
+
+    #[error("Cannot find the payload header '==[ Payload ]' in layer {layer}")]
+    MissingPayloadHeader { layer: usize },
+
+    #[error("Cannot find the payload after its header in layer {layer}")]
+    MissingPayloadMarker { layer: usize },
+
+    #[error(
+        "Cannot find the end of the payload '~>' in layer {layer}, it starts at byte {offset}"
+    )]
+    MissingPayloadTerminator { layer: usize, offset: usize },
+
+    #[error("Cannot detect the encoding of the payload of layer {layer}")]
+    UnknownEncoding { layer: usize },
+
+    #[error("Invalid {encoding} data in the payload of layer {layer}: {error}")]
+    TextDecoding {
+        layer: usize,
+        encoding: &'static str,
+        error: DecodeError,
+    },
+
+    #[error("Malformed packet in layer {layer} at byte {offset}")]
+    MalformedPacket { layer: usize, offset: usize },
+
+    #[error("The payload of layer {layer} is too short: {length} bytes")]
+    PayloadTooShort { layer: usize, length: usize },
+
+    #[error("Key unwrap integrity check failed in layer {layer} for the key at byte {offset}")]
+    KeyUnwrapIntegrity { layer: usize, offset: usize },
+
+    #[error("Cannot find the XOR key of layer {layer}")]
+    XorKeyNotFound { layer: usize },
+
+    #[error("Invalid key length {length}, the key must have at least 1 byte")]
+    InvalidKeyLength { length: usize },
+
+    #[error(
+        "The crib of {length} bytes at offset {offset} must fit in the {size} bytes of ciphertext"
+    )]
+    CribOutOfRange {
+        offset: usize,
+        length: usize,
+        size: usize,
+    },
+
+    #[error("The crib needs two different key bytes at key position {position}")]
+    CribConflict { position: usize },
+
+    #[error("The offset {offset} must be within the {size} bytes of ciphertext")]
+    OffsetOutOfRange { offset: usize, size: usize },
+
+    #[error("Invalid opcode {opcode:#04x} in layer {layer} at address {offset:#010x}")]
+    InvalidOpcode {
+        layer: usize,
+        offset: usize,
+        opcode: u8,
+    },
+
+    /*
+        Raised on memory access out of bounds and when a program runs out of
+        its instruction budget without halting
+    */
+    #[error("Virtual machine fault in layer {layer} at address {offset:#010x}")]
+    VmFault { layer: usize, offset: usize },
+}
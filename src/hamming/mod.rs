@@ -0,0 +1,258 @@
+use std::fmt;
+
+use crate::bits::{BitOrder, BitReader, BitWriter};
+use crate::error::Result;
+use crate::helpers::format_offsets;
+use crate::layer::Layer;
+
+/*
+    An error correcting variant of layer 2: every byte of the payload is a
+    codeword carrying 4 data bits, most significant nibble first.
+
+    The bits of a codeword are numbered 1 to 7 from the most significant
+    bit: p1 p2 d1 p3 d2 d3 d4, where p1 covers the positions 1, 3, 5 and 7,
+    p2 the positions 2, 3, 6 and 7, and p3 the positions 4, 5, 6 and 7. The
+    least significant bit is unused by Hamming(7,4), with SECDED it makes
+    the count of '1' bits of the whole byte even.
+*/
+pub struct HammingLayer {
+    code: HammingCode,
+}
+
+impl HammingLayer {
+    pub fn new(code: HammingCode) -> HammingLayer {
+        HammingLayer { code }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HammingCode {
+    /*
+        Corrects any single bit error, two errors are miscorrected.
+    */
+    Hamming74,
+    /*
+        Extended Hamming(8,4): corrects single bit errors and detects two.
+    */
+    Secded,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Codeword {
+    Valid(u8),
+    Corrected(u8),
+    Uncorrectable,
+}
+
+/*
+    What happened to the codewords of the payload: the offsets of the ones
+    with a corrected error, of the uncorrectable ones which are decoded as
+    0 to keep the following nibbles in place, and how many data bits were
+    left over at the end.
+*/
+#[derive(Debug, PartialEq)]
+pub struct HammingStats {
+    pub total_codewords: usize,
+    pub corrected_offsets: Vec<usize>,
+    pub uncorrectable_offsets: Vec<usize>,
+    pub leftover_bits: usize,
+}
+
+impl fmt::Display for HammingStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} codewords, {} corrected",
+            self.total_codewords,
+            self.corrected_offsets.len()
+        )?;
+        if !self.corrected_offsets.is_empty() {
+            write!(f, " {}", format_offsets(&self.corrected_offsets))?;
+        }
+        write!(f, ", {} uncorrectable", self.uncorrectable_offsets.len())?;
+        if !self.uncorrectable_offsets.is_empty() {
+            write!(f, " {}", format_offsets(&self.uncorrectable_offsets))?;
+        }
+        write!(f, ", {} leftover data bits", self.leftover_bits)
+    }
+}
+
+impl Layer for HammingLayer {
+    fn name(&self) -> &'static str {
+        "Hamming Code"
+    }
+
+    fn index(&self) -> usize {
+        2
+    }
+
+    fn peel(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        Ok(decode(payload, self.code).0)
+    }
+
+    fn peel_with_summary(&self, payload: &[u8]) -> Result<(Vec<u8>, Option<String>)> {
+        let (data, stats): (Vec<u8>, HammingStats) = decode(payload, self.code);
+        Ok((data, Some(stats.to_string())))
+    }
+}
+
+pub fn decode(payload: &[u8], code: HammingCode) -> (Vec<u8>, HammingStats) {
+    let mut writer: BitWriter = BitWriter::new(BitOrder::MsbFirst);
+    let mut corrected_offsets: Vec<usize> = Vec::new();
+    let mut uncorrectable_offsets: Vec<usize> = Vec::new();
+
+    for (offset, byte) in payload.iter().enumerate() {
+        match decode_codeword(*byte, code) {
+            Codeword::Valid(nibble) => writer.push_bits(nibble as u64, 4),
+            Codeword::Corrected(nibble) => {
+                corrected_offsets.push(offset);
+                writer.push_bits(nibble as u64, 4);
+            }
+            Codeword::Uncorrectable => {
+                uncorrectable_offsets.push(offset);
+                writer.push_bits(0, 4);
+            }
+        }
+    }
+
+    let stats: HammingStats = HammingStats {
+        total_codewords: payload.len(),
+        corrected_offsets,
+        uncorrectable_offsets,
+        leftover_bits: writer.leftover_bits(),
+    };
+    (writer.into_bytes(), stats)
+}
+
+/*
+    One codeword per nibble of `data`, most significant nibble first.
+*/
+pub fn encode(data: &[u8], code: HammingCode) -> Vec<u8> {
+    let mut reader: BitReader = BitReader::new(data, BitOrder::MsbFirst);
+    let mut encoded: Vec<u8> = Vec::with_capacity(data.len() * 2);
+    while let Some(nibble) = reader.read_bits(4) {
+        encoded.push(encode_codeword(nibble as u8, code));
+    }
+    encoded
+}
+
+pub fn encode_codeword(nibble: u8, code: HammingCode) -> u8 {
+    let (d1, d2, d3, d4): (u8, u8, u8, u8) = (
+        (nibble >> 3) & 1,
+        (nibble >> 2) & 1,
+        (nibble >> 1) & 1,
+        nibble & 1,
+    );
+    let p1: u8 = d1 ^ d2 ^ d4;
+    let p2: u8 = d1 ^ d3 ^ d4;
+    let p3: u8 = d2 ^ d3 ^ d4;
+
+    let codeword: u8 =
+        (p1 << 7) | (p2 << 6) | (d1 << 5) | (p3 << 4) | (d2 << 3) | (d3 << 2) | (d4 << 1);
+    match code {
+        HammingCode::Hamming74 => codeword,
+        HammingCode::Secded => codeword | (codeword.count_ones() % 2) as u8,
+    }
+}
+
+pub fn decode_codeword(codeword: u8, code: HammingCode) -> Codeword {
+    let bit = |position: u8| -> u8 { (codeword >> (8 - position)) & 1 };
+    let syndrome: u8 = (bit(1) ^ bit(3) ^ bit(5) ^ bit(7))
+        | (bit(2) ^ bit(3) ^ bit(6) ^ bit(7)) << 1
+        | (bit(4) ^ bit(5) ^ bit(6) ^ bit(7)) << 2;
+
+    let corrected: u8 = if syndrome == 0 {
+        codeword
+    } else {
+        codeword ^ (1 << (8 - syndrome))
+    };
+    let nibble: u8 = ((corrected >> 2) & 0b1000) | ((corrected >> 1) & 0b0111);
+
+    let single_error: bool = match code {
+        HammingCode::Hamming74 => syndrome != 0,
+        HammingCode::Secded => {
+            let odd_parity: bool = codeword.count_ones() % 2 == 1;
+            if syndrome != 0 && !odd_parity {
+                return Codeword::Uncorrectable;
+            }
+            odd_parity
+        }
+    };
+    if single_error {
+        Codeword::Corrected(nibble)
+    } else {
+        Codeword::Valid(nibble)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hamming::{
+        decode, decode_codeword, encode, encode_codeword, Codeword, HammingCode, HammingLayer,
+        HammingStats,
+    };
+    use crate::layer::Layer;
+
+    #[test]
+    fn codeword_test() {
+        for code in [HammingCode::Hamming74, HammingCode::Secded] {
+            for nibble in 0..16 {
+                let codeword: u8 = encode_codeword(nibble, code);
+                assert_eq!(Codeword::Valid(nibble), decode_codeword(codeword, code));
+                for position in 0..7 {
+                    let flipped: u8 = codeword ^ (0x80 >> position);
+                    assert_eq!(Codeword::Corrected(nibble), decode_codeword(flipped, code));
+                }
+            }
+        }
+
+        // 1011 is p1 p2 d1 p3 d2 d3 d4 = 0 1 1 0 0 1 1
+        assert_eq!(0b01100110, encode_codeword(0b1011, HammingCode::Hamming74));
+        assert_eq!(0b01100110, encode_codeword(0b1011, HammingCode::Secded));
+        assert_eq!(
+            Codeword::Corrected(0b1011),
+            decode_codeword(0b01100111, HammingCode::Secded)
+        );
+        assert_eq!(
+            Codeword::Uncorrectable,
+            decode_codeword(0b01100110 ^ 0b00100100, HammingCode::Secded)
+        );
+    }
+
+    #[test]
+    fn decode_test() {
+        let mut encoded: Vec<u8> = encode(b"Hamming", HammingCode::Secded);
+        assert_eq!(14, encoded.len());
+        encoded[1] ^= 0x10;
+        encoded[6] ^= 0x01;
+        encoded.push(encoded[13] ^ 0x0C);
+
+        let (decoded, stats) = decode(&encoded, HammingCode::Secded);
+        assert_eq!(b"Hamming".to_vec(), decoded);
+        assert_eq!(
+            HammingStats {
+                total_codewords: 15,
+                corrected_offsets: vec![1, 6],
+                uncorrectable_offsets: vec![14],
+                leftover_bits: 4,
+            },
+            stats
+        );
+
+        // An uncorrectable codeword in the middle only loses its own nibble,
+        // the high nibble of the 'm' (0x6D)
+        let mut middle: Vec<u8> = encoded.clone();
+        middle[4] ^= 0x0C;
+        let (decoded, stats) = decode(&middle, HammingCode::Secded);
+        assert_eq!(b"Ha\x0Dming".to_vec(), decoded);
+        assert_eq!(vec![4, 14], stats.uncorrectable_offsets);
+
+        let (_, summary) = HammingLayer::new(HammingCode::Secded)
+            .peel_with_summary(&encoded)
+            .unwrap();
+        assert_eq!(
+            Some("15 codewords, 2 corrected at offsets 1, 6, 1 uncorrectable at offset 14, 4 leftover data bits".to_string()),
+            summary
+        );
+    }
+}
@@ -1,18 +1,162 @@
-use anyhow::{anyhow, Result};
+use crate::ascii85::{self, EncodeOptions};
+use crate::document::LayerDocument;
+use crate::encoding::{self, DetectError, Position};
+use crate::error::{OnionError, Result};
 
 /*
-    Takes in an ASCII85 encoded string slice and returns a Result<Vec<u8>>
+    Takes in the encoded payload of a layer, detects its encoding and returns a
+    Result<Vec<u8>>. The position of an invalid data error is relative to the start
+    of `encoded`.
 */
-pub(crate) fn decode(encoded: &str) -> Result<Vec<u8>> {
-    ascii85::decode(encoded).map_err(|e| anyhow!(e.to_string()))
+pub fn decode(layer: usize, encoded: &[u8]) -> Result<Vec<u8>> {
+    match encoding::detect(encoded) {
+        Ok(detected) => Ok(detected.decoded),
+        Err(DetectError::Unknown) => Err(OnionError::UnknownEncoding { layer }),
+        Err(DetectError::Invalid { encoding, error }) => Err(OnionError::TextDecoding {
+            layer,
+            encoding,
+            error,
+        }),
+    }
 }
 
 /*
-    Get the index of the start of the layer's payload.
-    The index is the start of ASCII85 '<~'
+    Decodes the payload of `document`, parsed from `content`. The position of
+    an invalid data error is relative to the start of `content`, so it points
+    at the character in the whole layer rather than in its payload.
 */
-pub(crate) fn get_layer_start_index(string: &str) -> usize {
-    string
-        .find("<~")
-        .expect("Cannot find the start of the layer '<~' in payload")
+pub fn decode_payload(layer: usize, content: &[u8], document: &LayerDocument) -> Result<Vec<u8>> {
+    decode(layer, document.payload).map_err(|error| match error {
+        OnionError::TextDecoding {
+            layer,
+            encoding,
+            mut error,
+        } => {
+            let offset: usize = document.payload_offset + error.position.offset;
+            error.position = Position::locate(content, offset);
+            OnionError::TextDecoding {
+                layer,
+                encoding,
+                error,
+            }
+        }
+        error => error,
+    })
+}
+
+/*
+    Encodes `data` the way the payloads of the onion are: Adobe ASCII85
+    between '<~' and '~>', wrapped at 60 columns.
+*/
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    ascii85::encode(data, &EncodeOptions::default())
+}
+
+/*
+    Presents bytes as text without losing any of them: valid UTF-8 is kept
+    as is, anything else is decoded as Latin-1 where every byte is a char.
+*/
+pub fn to_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&byte| byte as char).collect(),
+    }
+}
+
+/*
+    Lists offsets for a summary, e.g. 'at offsets 3, 17, 42', only the first
+    few of them when there are many.
+*/
+pub fn format_offsets(offsets: &[usize]) -> String {
+    const MAX_LISTED: usize = 8;
+
+    let mut listed: Vec<String> = offsets
+        .iter()
+        .take(MAX_LISTED)
+        .map(|offset| offset.to_string())
+        .collect();
+    if offsets.len() > MAX_LISTED {
+        listed.push("...".to_string());
+    }
+    match offsets.len() {
+        0 => String::new(),
+        1 => format!("at offset {}", listed[0]),
+        _ => format!("at offsets {}", listed.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::LayerDocument;
+    use crate::encoding::Position;
+    use crate::error::OnionError;
+    use crate::helpers::{decode, decode_payload, encode, format_offsets, to_text};
+
+    #[test]
+    fn decode_test() {
+        assert_eq!(
+            b"Man sure.".to_vec(),
+            decode(0, b"<~9jqo^F*2M7/c~>").unwrap()
+        );
+
+        match decode(2, b"<~9jqo^F*2M7\n/c{~>") {
+            Err(OnionError::TextDecoding {
+                layer,
+                encoding,
+                error,
+            }) => {
+                assert_eq!(2, layer);
+                assert_eq!("Adobe ASCII85", encoding);
+                assert_eq!(15, error.position.offset);
+            }
+            _ => panic!("Expected an ASCII85 error"),
+        }
+    }
+
+    #[test]
+    fn decode_payload_test() {
+        let content: &[u8] = b"==[ Layer 2/6: Parity Bit ]==\n\nSome instructions.\n\n\
+            ==[ Payload ]==\n\n<~9jqo^F*2M7\n/c{~>";
+        let document: LayerDocument = LayerDocument::parse(2, content).unwrap();
+
+        match decode_payload(2, content, &document) {
+            Err(OnionError::TextDecoding { error, .. }) => {
+                let offset: usize = content.iter().position(|&byte| byte == b'{').unwrap();
+                assert_eq!(
+                    Position {
+                        offset,
+                        line: 8,
+                        column: 3,
+                    },
+                    error.position
+                );
+            }
+            _ => panic!("Expected an ASCII85 error"),
+        }
+    }
+
+    #[test]
+    fn encode_payload() {
+        let content: &[u8] = include_bytes!("../../payload");
+        let document: LayerDocument = LayerDocument::parse(0, content).unwrap();
+        let decoded: Vec<u8> = decode(0, document.payload).unwrap();
+        assert_eq!(document.payload.to_vec(), encode(&decoded));
+    }
+
+    #[test]
+    fn to_text_test() {
+        assert_eq!("==[ Layer ]==", to_text(b"==[ Layer ]=="));
+        assert_eq!("caf\u{e9}", to_text("caf\u{e9}".as_bytes()));
+        assert_eq!("\u{ff}A\u{80}", to_text(&[0xFF, 0x41, 0x80]));
+    }
+
+    #[test]
+    fn format_offsets_test() {
+        assert_eq!("", format_offsets(&[]));
+        assert_eq!("at offset 7", format_offsets(&[7]));
+        assert_eq!(
+            "at offsets 0, 1, 2, 3, 4, 5, 6, 7, ...",
+            format_offsets(&(0..10).collect::<Vec<usize>>())
+        );
+    }
 }
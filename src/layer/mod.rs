@@ -0,0 +1,80 @@
+use crate::error::Result;
+
+use crate::layer_five::LayerFive;
+use crate::layer_four::LayerFour;
+use crate::layer_one::LayerOne;
+use crate::layer_six::LayerSix;
+use crate::layer_three::LayerThree;
+use crate::layer_two::LayerTwo;
+use crate::layer_zero::LayerZero;
+
+/*
+    A layer of the onion. Peeling takes the ASCII85 decoded payload of the
+    layer and returns the content of the next layer.
+*/
+pub trait Layer {
+    fn name(&self) -> &'static str;
+
+    fn index(&self) -> usize;
+
+    fn peel(&self, payload: &[u8]) -> Result<Vec<u8>>;
+
+    /*
+        Same as `peel`, with a summary of what peeling did to the payload,
+        e.g. how many bytes were discarded. Layers with nothing worth
+        reporting only peel.
+    */
+    fn peel_with_summary(&self, payload: &[u8]) -> Result<(Vec<u8>, Option<String>)> {
+        Ok((self.peel(payload)?, None))
+    }
+}
+
+/*
+    Every layer of the onion, ordered from the outermost layer to the core.
+*/
+pub fn registry() -> Vec<Box<dyn Layer>> {
+    vec![
+        Box::new(LayerZero),
+        Box::new(LayerOne),
+        Box::new(LayerTwo::default()),
+        Box::new(LayerThree::default()),
+        Box::new(LayerFour),
+        Box::new(LayerFive),
+        Box::new(LayerSix),
+    ]
+}
+
+/*
+    Replaces the layer of `registry` that has the same index as `layer`, e.g.
+    with a layer configured from the command line. The layer is added when
+    the registry has no layer with its index.
+*/
+pub fn replace(registry: &mut Vec<Box<dyn Layer>>, layer: Box<dyn Layer>) {
+    match registry
+        .iter()
+        .position(|registered| registered.index() == layer.index())
+    {
+        Some(position) => registry[position] = layer,
+        None => registry.push(layer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hamming::{HammingCode, HammingLayer};
+    use crate::layer::{registry, replace, Layer};
+
+    #[test]
+    fn replace_test() {
+        let mut layers: Vec<Box<dyn Layer>> = registry();
+        layers.reverse();
+        replace(
+            &mut layers,
+            Box::new(HammingLayer::new(HammingCode::Secded)),
+        );
+
+        assert_eq!(7, layers.len());
+        assert_eq!("Hamming Code", layers[4].name());
+        assert_eq!(2, layers[4].index());
+    }
+}
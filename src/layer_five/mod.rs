@@ -0,0 +1,252 @@
+use crate::error::{OnionError, Result};
+use crate::layer::Layer;
+use crate::rng::Rng;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::Aes256;
+
+/*
+==[ Layer 5/6: Advanced Encryption Standard ]===============
+
+The Advanced Encryption Standard (AES) is an
+industry-standard encryption algorithm. In 2001, after a
+five year evaluation of 15 different encryption algorithms,
+this algorithm was selected as the standard for use by the
+U.S. Federal Government. In 2003, the National Security
+Agency announced that AES was sufficient to protect the
+highest level of classified information: TOP SECRET. Since
+then it has seen wide adoption.
+
+Currently, there are no known attacks capable of breaking
+AES encryption when implemented properly. It is generally
+considered to be one of the strongest and safest encryption
+algorithms.
+
+    ----------------------------------------------------
+
+This payload has been encrypted with AES-256 in Counter Mode
+(CTR). To decrypt the payload you will need the encryption
+key and the initialization vector (IV). It is not possible
+to guess these, so I will just give them to you. They are at
+the start of the payload.
+
+But... surprise! The key is also encrypted with AES. It
+turns out that the U.S. Government also has standards for
+how to encrypt encryption keys. I've encrypted the key using
+the AES Key Wrap algorithm specified in RFC 3394. How do you
+decrypt the key? Well, you will need another key, called the
+"key encrypting key" (KEK), and another initialization
+vector. These are also impossible to guess, so I will just
+give them to you. They are also at the start of the payload.
+
+But... surprise! Just kidding. I haven't encrypted the KEK.
+The U.S. Government does not have a standard for encrypting
+key encrypting keys, as far as I'm aware. That would be a
+bit too crazy.
+
+The payload is structured like this:
+
+ - First 32 bytes: The 256-bit key encrypting key (KEK).
+ - Next 8 bytes: The 64-bit initialization vector (IV) for
+   the wrapped key.
+ - Next 40 bytes: The wrapped (encrypted) key. When
+   decrypted, this will become the 256-bit encryption key.
+ - Next 16 bytes: The 128-bit initialization vector (IV) for
+   the encrypted payload.
+ - All remaining bytes: The encrypted payload.
+
+The first step is to use the KEK and the 64-bit IV to unwrap
+the wrapped key. The second step is to use the unwrapped key
+and the 128-bit IV to decrypt the rest of the payload.
+
+Don't try to write the decryption algorithms yourself. Or
+do. I'm not your dad. You do you. Personally, I used OpenSSL
+to generate the payload for this layer, and reused the
+`aes_key_wrap` Ruby gem that I wrote years ago.
+
+
+==[ Payload ]===============================================
+*/
+pub struct LayerFive;
+
+impl Layer for LayerFive {
+    fn name(&self) -> &'static str {
+        "Advanced Encryption Standard"
+    }
+
+    fn index(&self) -> usize {
+        LAYER
+    }
+
+    fn peel(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        if payload.len() < ENCRYPTED_PAYLOAD_START {
+            return Err(OnionError::PayloadTooShort {
+                layer: LAYER,
+                length: payload.len(),
+            });
+        }
+
+        let kek: &[u8] = &payload[0..32];
+        let key_iv: &[u8] = &payload[32..40];
+        let wrapped_key: &[u8] = &payload[WRAPPED_KEY_START..80];
+        let payload_iv: &[u8] = &payload[80..96];
+        let encrypted: &[u8] = &payload[ENCRYPTED_PAYLOAD_START..];
+
+        let key: Vec<u8> =
+            unwrap_key(kek, key_iv, wrapped_key).ok_or(OnionError::KeyUnwrapIntegrity {
+                layer: LAYER,
+                offset: WRAPPED_KEY_START,
+            })?;
+        let decrypted: Vec<u8> = aes_256_ctr(&key, payload_iv, encrypted);
+
+        Ok(decrypted)
+    }
+}
+
+const LAYER: usize = 5;
+const WRAPPED_KEY_START: usize = 40;
+const ENCRYPTED_PAYLOAD_START: usize = 96;
+
+/*
+    The payload that peels to `data`, with a random KEK, key and IVs.
+*/
+pub fn encode(data: &[u8], rng: &mut Rng) -> Vec<u8> {
+    let kek: Vec<u8> = rng.bytes(32);
+    let key_iv: Vec<u8> = rng.bytes(8);
+    let key: Vec<u8> = rng.bytes(32);
+    let payload_iv: Vec<u8> = rng.bytes(16);
+
+    let mut payload: Vec<u8> = Vec::with_capacity(ENCRYPTED_PAYLOAD_START + data.len());
+    payload.extend_from_slice(&kek);
+    payload.extend_from_slice(&key_iv);
+    payload.extend_from_slice(&wrap_key(&kek, &key_iv, &key));
+    payload.extend_from_slice(&payload_iv);
+    payload.extend_from_slice(&aes_256_ctr(&key, &payload_iv, data));
+    payload
+}
+
+/*
+    AES Key Wrap as specified in RFC 3394 section 2.2.1, the key length must
+    be a multiple of 8 bytes.
+*/
+fn wrap_key(kek: &[u8], iv: &[u8], key: &[u8]) -> Vec<u8> {
+    let cipher: Aes256 = Aes256::new(GenericArray::from_slice(kek));
+    let n: usize = key.len() / 8;
+    let mut a: [u8; 8] = [0; 8];
+    a.copy_from_slice(iv);
+    let mut r: Vec<u8> = key.to_vec();
+
+    for j in 0..6 {
+        for i in 1..=n {
+            let t: u64 = (n * j + i) as u64;
+            let mut block: [u8; 16] = [0; 16];
+            block[0..8].copy_from_slice(&a);
+            block[8..16].copy_from_slice(&r[(i - 1) * 8..i * 8]);
+
+            cipher.encrypt_block(GenericArray::from_mut_slice(&mut block));
+
+            let mut msb: [u8; 8] = [0; 8];
+            msb.copy_from_slice(&block[0..8]);
+            a = (u64::from_be_bytes(msb) ^ t).to_be_bytes();
+            r[(i - 1) * 8..i * 8].copy_from_slice(&block[8..16]);
+        }
+    }
+
+    let mut wrapped: Vec<u8> = a.to_vec();
+    wrapped.extend_from_slice(&r);
+    wrapped
+}
+
+/*
+    AES Key Unwrap as specified in RFC 3394 section 2.2.2, returns None when
+    the integrity check fails, i.e. the recovered IV does not match the
+    expected one, or when the KEK or wrapped key have an invalid length.
+*/
+fn unwrap_key(kek: &[u8], iv: &[u8], wrapped_key: &[u8]) -> Option<Vec<u8>> {
+    if !wrapped_key.len().is_multiple_of(8) || wrapped_key.len() < 24 {
+        return None;
+    }
+
+    let cipher: Aes256 = Aes256::new_from_slice(kek).ok()?;
+    let n: usize = wrapped_key.len() / 8 - 1;
+    let mut a: [u8; 8] = [0; 8];
+    a.copy_from_slice(&wrapped_key[0..8]);
+    let mut r: Vec<u8> = wrapped_key[8..].to_vec();
+
+    for j in (0..6).rev() {
+        for i in (1..=n).rev() {
+            let t: u64 = (n * j + i) as u64;
+            let mut block: [u8; 16] = [0; 16];
+            block[0..8].copy_from_slice(&(u64::from_be_bytes(a) ^ t).to_be_bytes());
+            block[8..16].copy_from_slice(&r[(i - 1) * 8..i * 8]);
+
+            cipher.decrypt_block(GenericArray::from_mut_slice(&mut block));
+
+            a.copy_from_slice(&block[0..8]);
+            r[(i - 1) * 8..i * 8].copy_from_slice(&block[8..16]);
+        }
+    }
+
+    if a[..] != iv[..] {
+        return None;
+    }
+    Some(r)
+}
+
+/*
+    AES-256 in counter mode, the IV is the initial counter block and is
+    incremented as a 128 bits big endian integer for every block.
+*/
+fn aes_256_ctr(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+    let cipher: Aes256 = Aes256::new(GenericArray::from_slice(key));
+    let mut initial_counter: [u8; 16] = [0; 16];
+    initial_counter.copy_from_slice(iv);
+    let mut counter: u128 = u128::from_be_bytes(initial_counter);
+
+    let mut output: Vec<u8> = Vec::with_capacity(data.len());
+    for chunk in data.chunks(16) {
+        let mut keystream = GenericArray::from(counter.to_be_bytes());
+        cipher.encrypt_block(&mut keystream);
+        output.extend(chunk.iter().zip(keystream.iter()).map(|(c, k)| c ^ k));
+        counter = counter.wrapping_add(1);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layer::Layer;
+    use crate::layer_five::{encode, unwrap_key, wrap_key, LayerFive};
+    use crate::rng::Rng;
+
+    #[test]
+    fn unwrap_key_test() {
+        // RFC 3394 section 4.6: wrap 256 bits of key data with a 256-bit KEK
+        let kek: Vec<u8> = (0x00..=0x1F).collect();
+        let iv: Vec<u8> = vec![0xA6; 8];
+        let wrapped_key: Vec<u8> = vec![
+            0x28, 0xC9, 0xF4, 0x04, 0xC4, 0xB8, 0x10, 0xF4, 0xCB, 0xCC, 0xB3, 0x5C, 0xFB, 0x87,
+            0xF8, 0x26, 0x3F, 0x57, 0x86, 0xE2, 0xD8, 0x0E, 0xD3, 0x26, 0xCB, 0xC7, 0xF0, 0xE7,
+            0x1A, 0x99, 0xF4, 0x3B, 0xFB, 0x98, 0x8B, 0x9B, 0x7A, 0x02, 0xDD, 0x21,
+        ];
+        let key_data: Vec<u8> = vec![
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD,
+            0xEE, 0xFF, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B,
+            0x0C, 0x0D, 0x0E, 0x0F,
+        ];
+        assert_eq!(key_data, unwrap_key(&kek, &iv, &wrapped_key).unwrap());
+        assert_eq!(wrapped_key, wrap_key(&kek, &iv, &key_data));
+
+        let mut corrupted: Vec<u8> = wrapped_key.clone();
+        corrupted[20] ^= 0x01;
+        assert!(unwrap_key(&kek, &iv, &corrupted).is_none());
+    }
+
+    #[test]
+    fn encode_test() {
+        let data: &[u8] = b"==[ Layer 6/6: Virtual Machine ]";
+        let payload: Vec<u8> = encode(data, &mut Rng::new(5));
+        assert_eq!(96 + data.len(), payload.len());
+        assert_eq!(data.to_vec(), LayerFive.peel(&payload).unwrap());
+    }
+}
@@ -1,6 +1,9 @@
-use anyhow::Result;
-
-use crate::helpers;
+use crate::error::{OnionError, Result};
+use crate::layer::Layer;
+use crate::packets::{
+    ones_complement_sum, DESTINATION_ADDRESS, DESTINATION_PORT, IPV4_HEADER_LEN, SOURCE_ADDRESS,
+    UDP_HEADER_LEN, UDP_PROTOCOL,
+};
 
 /*
 ==[ Layer 4/6: Network Traffic ]============================
@@ -85,8 +88,132 @@ necessary.
 
 ==[ Payload ]===============================================
 */
-pub(crate) fn decode(encoded: &str) -> Result<String> {
-    let _payload: Vec<u8> = helpers::decode(encoded)?;
+pub struct LayerFour;
+
+impl Layer for LayerFour {
+    fn name(&self) -> &'static str {
+        "Network Traffic"
+    }
+
+    fn index(&self) -> usize {
+        4
+    }
+
+    fn peel(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> = Vec::new();
+        let mut offset: usize = 0;
+        while offset + IPV4_HEADER_LEN + UDP_HEADER_LEN <= payload.len() {
+            let total_length: usize = read_u16(payload, offset + 2) as usize;
+            if total_length < IPV4_HEADER_LEN + UDP_HEADER_LEN
+                || offset + total_length > payload.len()
+            {
+                return Err(OnionError::MalformedPacket {
+                    layer: self.index(),
+                    offset,
+                });
+            }
+
+            let packet: &[u8] = &payload[offset..offset + total_length];
+            if is_valid_packet(packet) {
+                data.extend_from_slice(&packet[IPV4_HEADER_LEN + UDP_HEADER_LEN..]);
+            }
+            offset += total_length;
+        }
+
+        Ok(data)
+    }
+}
+
+/*
+    A packet is part of the solution when it was sent from 10.1.1.10 to
+    10.1.1.200:42069 and both the IPv4 and UDP checksums are correct.
+*/
+fn is_valid_packet(packet: &[u8]) -> bool {
+    let ipv4_header: &[u8] = &packet[..IPV4_HEADER_LEN];
+    let udp_segment: &[u8] = &packet[IPV4_HEADER_LEN..];
+
+    ipv4_header[12..16] == SOURCE_ADDRESS
+        && ipv4_header[16..20] == DESTINATION_ADDRESS
+        && read_u16(udp_segment, 2) == DESTINATION_PORT
+        && read_u16(udp_segment, 4) as usize == udp_segment.len()
+        && ipv4_checksum_is_valid(ipv4_header)
+        && udp_checksum_is_valid(ipv4_header, udp_segment)
+}
+
+/*
+    Summing the header including its checksum field must give 0xFFFF.
+*/
+fn ipv4_checksum_is_valid(ipv4_header: &[u8]) -> bool {
+    ones_complement_sum(0, ipv4_header) == 0xFFFF
+}
+
+/*
+    The UDP checksum covers a pseudo header (source and destination
+    addresses, protocol and UDP length) followed by the UDP header and data.
+    A checksum of zero means the sender did not compute one.
+*/
+fn udp_checksum_is_valid(ipv4_header: &[u8], udp_segment: &[u8]) -> bool {
+    if read_u16(udp_segment, 6) == 0 {
+        return true;
+    }
+
+    let mut pseudo_header: Vec<u8> = Vec::with_capacity(12);
+    pseudo_header.extend_from_slice(&ipv4_header[12..20]);
+    pseudo_header.push(0);
+    pseudo_header.push(UDP_PROTOCOL);
+    pseudo_header.extend_from_slice(&(udp_segment.len() as u16).to_be_bytes());
+
+    let sum: u16 = ones_complement_sum(ones_complement_sum(0, &pseudo_header), udp_segment);
+    sum == 0xFFFF
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+}
 
-    Ok("".to_string())
+#[cfg(test)]
+mod tests {
+    use crate::layer_four::{ipv4_checksum_is_valid, is_valid_packet, udp_checksum_is_valid};
+    use crate::packets::{decoy_packet, Decoy, DESTINATION_ADDRESS, SOURCE_ADDRESS};
+    use crate::rng::Rng;
+
+    #[test]
+    fn checksum_test() {
+        // Example header from https://en.wikipedia.org/wiki/IPv4_header_checksum
+        let ipv4_header: Vec<u8> = vec![
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0x61, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        assert!(ipv4_checksum_is_valid(&ipv4_header));
+
+        let mut corrupted: Vec<u8> = ipv4_header.clone();
+        corrupted[10] ^= 0x01;
+        assert!(!ipv4_checksum_is_valid(&corrupted));
+
+        // UDP segment with an odd sized data section "abc" and its checksum
+        let udp_segment: Vec<u8> = vec![
+            0x30, 0x39, 0xa4, 0x55, 0x00, 0x0b, 0xe4, 0xcd, 0x61, 0x62, 0x63,
+        ];
+        assert!(udp_checksum_is_valid(&ipv4_header, &udp_segment));
+
+        let mut corrupted: Vec<u8> = udp_segment.clone();
+        corrupted[8] ^= 0x01;
+        assert!(!udp_checksum_is_valid(&ipv4_header, &corrupted));
+    }
+
+    #[test]
+    fn truncated_packet_test() {
+        // Everything is right but the UDP length, which counts the cut bytes
+        let mut rng: Rng = Rng::new(3);
+        for _ in 0..100 {
+            let packet: Vec<u8> = decoy_packet(Decoy::Truncated, &rng.bytes(32), &mut rng);
+            let (ipv4_header, udp_segment) = packet.split_at(20);
+            assert_eq!(SOURCE_ADDRESS, ipv4_header[12..16]);
+            assert_eq!(DESTINATION_ADDRESS, ipv4_header[16..20]);
+            assert_eq!(&[0xa4, 0x55], &udp_segment[2..4]);
+            assert!(ipv4_checksum_is_valid(ipv4_header));
+            assert!(udp_checksum_is_valid(ipv4_header, udp_segment));
+            assert!(!is_valid_packet(&packet));
+        }
+    }
 }
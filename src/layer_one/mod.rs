@@ -1,6 +1,7 @@
-use anyhow::{anyhow, Result};
+use crate::error::Result;
 
-use crate::helpers;
+use crate::layer::Layer;
+use crate::transform::{ByteOp, Transform};
 
 /*
 ==[ Layer 1/6: Bitwise Operations ]=========================
@@ -97,13 +98,53 @@ Here are some hints:
    need to mask it into a separate variable before doing the
    shift.
 */
-pub(crate) fn decode(encoded: &str) -> Result<String> {
-    let flip_mask: u8 = 0x55; // 0h01010101
-    helpers::decode(encoded).and_then(|mut vec| {
-        vec.iter_mut().for_each(|byte| {
-            let flipped: u8 = *byte ^ flip_mask;
-            *byte = (flipped >> 1) | (((flipped) & 0x01) << 7);
-        });
-        String::from_utf8(vec).map_err(|e| anyhow!(e.to_string()))
-    })
+pub struct LayerOne;
+
+impl Layer for LayerOne {
+    fn name(&self) -> &'static str {
+        "Bitwise Operations"
+    }
+
+    fn index(&self) -> usize {
+        1
+    }
+
+    fn peel(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        Ok(transform().apply(payload))
+    }
+}
+
+/*
+    Flip every second bit, then rotate to the right.
+*/
+pub fn transform() -> Transform {
+    Transform::default()
+        .then(ByteOp::Xor(0x55)) // 0b01010101
+        .then(ByteOp::RotateRight(1))
+}
+
+/*
+    The payload that peels to `data`.
+*/
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    transform().inverse().apply(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layer::Layer;
+    use crate::layer_one::{encode, LayerOne};
+
+    #[test]
+    fn peel_test() {
+        // The example of the instructions
+        assert_eq!(vec![0xF0], LayerOne.peel(&[0xB4]).unwrap());
+
+        let encoded: Vec<u8> = encode(b"==[ Layer 2/6: Parity Bit ]");
+        assert_eq!(vec![0xB4], encode(&[0xF0]));
+        assert_eq!(
+            b"==[ Layer 2/6: Parity Bit ]".to_vec(),
+            LayerOne.peel(&encoded).unwrap()
+        );
+    }
 }
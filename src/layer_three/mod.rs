@@ -1,6 +1,6 @@
-use anyhow::{anyhow, Result};
-
-use crate::helpers;
+use crate::error::{OnionError, Result};
+use crate::layer::Layer;
+use crate::xor::{self, Crib};
 
 /*
 ==[ Layer 3/6: XOR Encryption ]=============================
@@ -72,25 +72,102 @@ payload.
 
 ==[ Payload ]===============================================
 */
-pub(crate) fn decode(encoded: &str) -> Result<String> {
-    let encrypted: Vec<u8> = helpers::decode(encoded)?;
-
-    // Got the key from the test below
-    let key: Vec<u8> = vec![
-        108, 36, 132, 142, 66, 25, 168, 225, 197, 219, 87, 101, 185, 198, 20, 158, 165, 25, 53,
-        150, 59, 57, 127, 165, 101, 209, 254, 1, 133, 125, 217, 76,
-    ];
-
-    let mut decrypted_bytes: Vec<u8> = Vec::new();
-    for i in 0..encrypted.len() {
-        decrypted_bytes.push(encrypted[i] ^ key[i % 32]);
+/*
+    Without a key, the key is recovered from the payload.
+*/
+#[derive(Default)]
+pub struct LayerThree {
+    key: Option<Vec<u8>>,
+}
+
+impl LayerThree {
+    pub fn with_key(key: Vec<u8>) -> LayerThree {
+        LayerThree { key: Some(key) }
+    }
+}
+
+impl Layer for LayerThree {
+    fn name(&self) -> &'static str {
+        "XOR Encryption"
+    }
+
+    fn index(&self) -> usize {
+        3
+    }
+
+    fn peel(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        if let Some(key) = &self.key {
+            return Ok(xor::apply_key(payload, key));
+        }
+
+        let key: Vec<u8> = xor::solve(
+            payload,
+            MAX_KEY_LENGTH,
+            KEY_LENGTH_CANDIDATES,
+            &cribs(self.index() + 1),
+        )
+        .ok_or(OnionError::XorKeyNotFound {
+            layer: self.index(),
+        })?
+        .bytes;
+
+        Ok(xor::apply_key(payload, &key))
     }
+}
+
+// The instructions say the key is 32 bytes, but its length is estimated
+const MAX_KEY_LENGTH: usize = 64;
+const KEY_LENGTH_CANDIDATES: usize = 4;
 
-    String::from_utf8(decrypted_bytes).map_err(|e| anyhow!(e.to_string()))
+/*
+    Known plaintext of the next layer, worked out in the test below: its
+    title line starts with '==[ Layer N/6: ' and is padded with '=' up to 60
+    characters, followed by an empty line.
+*/
+fn cribs(next_layer: usize) -> Vec<Crib> {
+    vec![
+        Crib::new(0, format!("==[ Layer {}/6: ", next_layer).as_bytes()),
+        Crib::new(55, b"=====\n\n"),
+    ]
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::layer_three::{cribs, KEY_LENGTH_CANDIDATES, MAX_KEY_LENGTH};
+    use crate::peel_range;
+    use crate::xor::{apply_key, estimate_key_lengths, solve};
+
+    #[test]
+    fn recover_key_from_payload() {
+        let layer_3 = peel_range(include_bytes!("../../payload"), 0, 2).unwrap();
+        let encrypted: &[u8] = &layer_3[2].peeled;
+        let payload = peel_range(encrypted, 3, 3).unwrap().remove(0).payload;
+
+        // The key worked out by hand in the test below
+        let key: Vec<u8> = vec![
+            108, 36, 132, 142, 66, 25, 168, 225, 197, 219, 87, 101, 185, 198, 20, 158, 165, 25, 53,
+            150, 59, 57, 127, 165, 101, 209, 254, 1, 133, 125, 217, 76,
+        ];
+        let candidates = estimate_key_lengths(&payload, MAX_KEY_LENGTH);
+        assert!(candidates[..KEY_LENGTH_CANDIDATES]
+            .iter()
+            .any(|candidate| candidate.length == 32));
+
+        let recovered = solve(&payload, MAX_KEY_LENGTH, KEY_LENGTH_CANDIDATES, &cribs(4));
+        assert_eq!(key, recovered.unwrap().bytes);
+
+        // The same plaintext encrypted with another key is still recovered
+        let other_key: Vec<u8> = key.iter().map(|byte| byte.rotate_left(3) ^ 0x5A).collect();
+        let reencrypted: Vec<u8> = apply_key(&apply_key(&payload, &key), &other_key);
+        let recovered = solve(
+            &reencrypted,
+            MAX_KEY_LENGTH,
+            KEY_LENGTH_CANDIDATES,
+            &cribs(4),
+        );
+        assert_eq!(other_key, recovered.unwrap().bytes);
+    }
+
     #[test]
     fn decrypt() {
         // First 62 bytes of the encrypted data that should look something similar to this:
@@ -153,14 +230,23 @@ mod tests {
             key.push(encrypted[i] ^ decrypted_from_test_bytes[i]);
         }
 
-        // Use the output from this in the function above to decrypt the layer
-        println!("Key: {:?}", key);
+        // The key recovered from the payload in the test above
+        assert_eq!(
+            vec![
+                108, 36, 132, 142, 66, 25, 168, 225, 197, 219, 87, 101, 185, 198, 20, 158, 165, 25,
+                53, 150, 59, 57, 127, 165, 101, 209, 254, 1, 133, 125, 217, 76,
+            ],
+            key
+        );
 
         let mut decrypted_bytes: Vec<u8> = Vec::new();
         for i in 0..encrypted.len() {
             decrypted_bytes.push(encrypted[i] ^ key[i % 32]);
         }
         let decrypted = String::from_utf8(decrypted_bytes).unwrap();
-        println!("Decrypted: '{}'", decrypted);
+        assert_eq!(
+            "==[ Layer 4/6: Network Traffic ]============================\n\n",
+            decrypted
+        );
     }
 }
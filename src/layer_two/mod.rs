@@ -1,8 +1,11 @@
-use std::u64;
+use std::fmt;
 
-use anyhow::{anyhow, Result};
+use crate::bits::{BitOrder, BitReader, BitWriter};
+use crate::error::Result;
+use crate::helpers::format_offsets;
 
-use crate::helpers;
+use crate::layer::Layer;
+use crate::xor;
 
 /*
 ==[ Layer 2/6: Parity Bit ]=================================
@@ -41,56 +44,362 @@ of eight bytes contains 64 bits total, including 8 parity
 bits. Removing the 8 parity bits leaves behind 56 data
 bits, which is exactly 7 bytes.
 */
-pub(crate) fn decode(encoded: &str) -> Result<String> {
-    let vec: Vec<u8> = helpers::decode(encoded)?;
-    let filtered: Vec<u8> = vec
-        .into_iter()
-        .filter(|byte| -> bool {
-            let actual_parity: u8 = *byte & 0x01;
-            let calculated_parity: u8 = parity(*byte);
-            actual_parity == calculated_parity
+/*
+    Peels with the given parity scheme, or with the scheme under which the
+    most bytes of the payload are valid when none is given.
+*/
+#[derive(Default)]
+pub struct LayerTwo {
+    scheme: Option<ParityScheme>,
+}
+
+impl LayerTwo {
+    pub fn with_scheme(scheme: ParityScheme) -> LayerTwo {
+        LayerTwo {
+            scheme: Some(scheme),
+        }
+    }
+}
+
+/*
+    With `Even` parity the parity bit makes the count of '1' bits in the data
+    and parity bits even, which is the rule of this layer. With `Odd` it makes
+    that count odd.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parity {
+    Even,
+    Odd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParityBit {
+    Lsb,
+    Msb,
+}
+
+/*
+    How a byte carries its data: the parity rule, where the parity bit is,
+    and how many data bits (1 to 7) are right next to it. Any other bits of
+    the byte are ignored.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParityScheme {
+    parity: Parity,
+    parity_bit: ParityBit,
+    data_bits: usize,
+}
+
+impl Default for ParityScheme {
+    fn default() -> ParityScheme {
+        ParityScheme {
+            parity: Parity::Even,
+            parity_bit: ParityBit::Lsb,
+            data_bits: 7,
+        }
+    }
+}
+
+impl ParityScheme {
+    /*
+        None when `data_bits` is not between 1 and 7, a byte has room for the
+        parity bit and at most 7 data bits.
+    */
+    pub fn new(parity: Parity, parity_bit: ParityBit, data_bits: usize) -> Option<ParityScheme> {
+        if !(1..=7).contains(&data_bits) {
+            return None;
+        }
+        Some(ParityScheme {
+            parity,
+            parity_bit,
+            data_bits,
         })
-        .collect();
-    let chunks: std::slice::Chunks<u8> = filtered.chunks(8);
-    let mut combined: Vec<u8> = Vec::new();
+    }
+
+    pub fn parity(&self) -> Parity {
+        self.parity
+    }
+
+    pub fn parity_bit(&self) -> ParityBit {
+        self.parity_bit
+    }
+
+    pub fn data_bits(&self) -> usize {
+        self.data_bits
+    }
+
+    /*
+        Every scheme, widest data first, starting with the one of this layer.
+    */
+    pub fn all() -> Vec<ParityScheme> {
+        let mut schemes: Vec<ParityScheme> = Vec::new();
+        for data_bits in (1..=7).rev() {
+            for parity_bit in [ParityBit::Lsb, ParityBit::Msb] {
+                for parity in [Parity::Even, Parity::Odd] {
+                    schemes.push(ParityScheme {
+                        parity,
+                        parity_bit,
+                        data_bits,
+                    });
+                }
+            }
+        }
+        schemes
+    }
+
+    /*
+        The data bits of `byte`, None when its parity bit is incorrect.
+    */
+    pub fn check(&self, byte: u8) -> Option<u8> {
+        let (data, actual_parity): (u8, u8) = match self.parity_bit {
+            ParityBit::Lsb => ((byte >> 1) & self.data_mask(), byte & 0x01),
+            ParityBit::Msb => ((byte >> (7 - self.data_bits)) & self.data_mask(), byte >> 7),
+        };
+        let calculated_parity: u8 = match self.parity {
+            Parity::Even => parity(data << 1),
+            Parity::Odd => parity(data << 1) ^ 0x01,
+        };
+        if actual_parity == calculated_parity {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    /*
+        The result of `check` for every byte value.
+    */
+    pub fn table(&self) -> [Option<u8>; 256] {
+        let mut table: [Option<u8>; 256] = [None; 256];
+        for (byte, entry) in table.iter_mut().enumerate() {
+            *entry = self.check(byte as u8);
+        }
+        table
+    }
+
+    /*
+        The byte carrying `data` with a correct parity bit, the ignored bits
+        are 0.
+    */
+    pub fn encode_byte(&self, data: u8) -> u8 {
+        let data: u8 = data & self.data_mask();
+        let parity_value: u8 = match self.parity {
+            Parity::Even => parity(data << 1),
+            Parity::Odd => parity(data << 1) ^ 0x01,
+        };
+        match self.parity_bit {
+            ParityBit::Lsb => (data << 1) | parity_value,
+            ParityBit::Msb => (parity_value << 7) | (data << (7 - self.data_bits)),
+        }
+    }
+
+    /*
+        The parity and data bits of a byte, the other bits are ignored.
+    */
+    fn used_mask(&self) -> u8 {
+        match self.parity_bit {
+            ParityBit::Lsb => (self.data_mask() << 1) | 0x01,
+            ParityBit::Msb => 0x80 | (self.data_mask() << (7 - self.data_bits)),
+        }
+    }
+
+    fn data_mask(&self) -> u8 {
+        ((1u16 << self.data_bits) - 1) as u8
+    }
+}
+
+impl fmt::Display for ParityScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parity: &str = match self.parity {
+            Parity::Even => "even",
+            Parity::Odd => "odd",
+        };
+        let parity_bit: &str = match self.parity_bit {
+            ParityBit::Lsb => "LSB",
+            ParityBit::Msb => "MSB",
+        };
+        write!(
+            f,
+            "{} parity in the {}, {} data bits",
+            parity, parity_bit, self.data_bits
+        )
+    }
+}
+
+/*
+    What happened to the bytes of the payload: how many there were, the
+    offsets of the ones discarded for an incorrect parity bit, and how many
+    data bits were left over at the end, too few to make a whole byte.
+*/
+#[derive(Debug, PartialEq)]
+pub struct ParityStats {
+    pub total_bytes: usize,
+    pub discarded_offsets: Vec<usize>,
+    pub leftover_bits: usize,
+}
+
+impl ParityStats {
+    pub fn discarded_bytes(&self) -> usize {
+        self.discarded_offsets.len()
+    }
+}
+
+impl fmt::Display for ParityStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes, {} discarded for an incorrect parity bit",
+            self.total_bytes,
+            self.discarded_bytes()
+        )?;
+        if !self.discarded_offsets.is_empty() {
+            write!(f, " {}", format_offsets(&self.discarded_offsets))?;
+        }
+        write!(f, ", {} leftover data bits", self.leftover_bits)
+    }
+}
+
+impl Layer for LayerTwo {
+    fn name(&self) -> &'static str {
+        "Parity Bit"
+    }
+
+    fn index(&self) -> usize {
+        2
+    }
+
+    fn peel(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.peel_with_summary(payload)?.0)
+    }
+
+    fn peel_with_summary(&self, payload: &[u8]) -> Result<(Vec<u8>, Option<String>)> {
+        let scheme: ParityScheme = self.scheme.unwrap_or_else(|| detect_scheme(payload));
+        let (data, stats): (Vec<u8>, ParityStats) = decode_with_scheme(payload, scheme);
+        Ok((data, Some(format!("{}: {}", scheme, stats))))
+    }
+}
 
-    for chunk in chunks {
-        let mut byte_chunk: u64 = 0;
-        byte_chunk |= (chunk[0] >> 1) as u64;
-        byte_chunk = (byte_chunk << 7) | ((chunk[1] >> 1) as u64);
-        byte_chunk = (byte_chunk << 7) | ((chunk[2] >> 1) as u64);
-        byte_chunk = (byte_chunk << 7) | ((chunk[3] >> 1) as u64);
-        byte_chunk = (byte_chunk << 7) | ((chunk[4] >> 1) as u64);
-        byte_chunk = (byte_chunk << 7) | ((chunk[5] >> 1) as u64);
-        byte_chunk = (byte_chunk << 7) | ((chunk[6] >> 1) as u64);
-        byte_chunk = (byte_chunk << 7) | ((chunk[7] >> 1) as u64);
+/*
+    Combines the data bits of the bytes with a correct parity bit. When the
+    number of those bytes is not a multiple of 8, the last group still gives
+    the whole bytes it contains and its remaining bits are left over.
+*/
+pub fn decode(payload: &[u8]) -> (Vec<u8>, ParityStats) {
+    decode_with_scheme(payload, ParityScheme::default())
+}
+
+/*
+    Same as `decode` for bytes that carry their data as described by
+    `scheme`.
+*/
+pub fn decode_with_scheme(payload: &[u8], scheme: ParityScheme) -> (Vec<u8>, ParityStats) {
+    let table: [Option<u8>; 256] = scheme.table();
+    let capacity: usize = payload.len() * scheme.data_bits / 8;
+    let mut writer: BitWriter = BitWriter::with_capacity(BitOrder::MsbFirst, capacity);
+    let mut discarded_offsets: Vec<usize> = Vec::new();
+
+    // Groups of 8 valid bytes, the usual case, are pushed at once
+    for (group_index, group) in payload.chunks(8).enumerate() {
+        let mut group_data: u64 = 0;
+        let mut valid: bool = group.len() == 8;
+        for byte in group {
+            match table[*byte as usize] {
+                Some(data) => group_data = (group_data << scheme.data_bits) | data as u64,
+                None => valid = false,
+            }
+        }
+        if valid {
+            writer.push_bits(group_data, 8 * scheme.data_bits);
+            continue;
+        }
 
-        combined.push((byte_chunk >> 48) as u8);
-        combined.push((byte_chunk >> 40) as u8);
-        combined.push((byte_chunk >> 32) as u8);
-        combined.push((byte_chunk >> 24) as u8);
-        combined.push((byte_chunk >> 16) as u8);
-        combined.push((byte_chunk >> 8) as u8);
-        combined.push(byte_chunk as u8);
+        for (i, byte) in group.iter().enumerate() {
+            match table[*byte as usize] {
+                Some(data) => writer.push_bits(data as u64, scheme.data_bits),
+                None => discarded_offsets.push(group_index * 8 + i),
+            }
+        }
     }
 
-    String::from_utf8(combined).map_err(|e| anyhow!(e.to_string()))
+    let stats: ParityStats = ParityStats {
+        total_bytes: payload.len(),
+        discarded_offsets,
+        leftover_bits: writer.leftover_bits(),
+    };
+    (writer.into_bytes(), stats)
+}
+
+/*
+    Splits `data` in groups of `scheme.data_bits` bits, the last group is
+    padded with '0' bits, and gives one byte with a correct parity bit for
+    each group.
+*/
+pub fn encode(data: &[u8], scheme: ParityScheme) -> Vec<u8> {
+    let mut reader: BitReader = BitReader::new(data, BitOrder::MsbFirst);
+    let mut encoded: Vec<u8> = Vec::new();
+
+    while reader.remaining_bits() > 0 {
+        let length: usize = scheme.data_bits.min(reader.remaining_bits());
+        let group: u64 = reader.read_bits(length).unwrap_or_default();
+        let padded: u8 = (group << (scheme.data_bits - length)) as u8;
+        encoded.push(scheme.encode_byte(padded));
+    }
+    encoded
 }
 
+/*
+    The scheme under which the most bytes of the payload have a correct
+    parity bit and 0 in the ignored bits, as `encode` leaves them. Some
+    schemes always agree, e.g. even parity over 7 data bits with the parity
+    bit as LSB or MSB, so ties go to the scheme decoding to the most English
+    looking text on average, then to the first in `ParityScheme::all`.
+*/
+pub fn detect_scheme(payload: &[u8]) -> ParityScheme {
+    let valid_counts: Vec<(ParityScheme, usize)> = ParityScheme::all()
+        .into_iter()
+        .map(|scheme| {
+            let table: [Option<u8>; 256] = scheme.table();
+            let valid: usize = payload
+                .iter()
+                .filter(|byte| *byte & !scheme.used_mask() == 0 && table[**byte as usize].is_some())
+                .count();
+            (scheme, valid)
+        })
+        .collect();
+    let most_valid: usize = valid_counts
+        .iter()
+        .map(|(_, valid)| *valid)
+        .max()
+        .unwrap_or_default();
+
+    let mut best: Option<(ParityScheme, f64)> = None;
+    for (scheme, valid) in valid_counts {
+        if valid < most_valid {
+            continue;
+        }
+        let decoded: Vec<u8> = decode_with_scheme(payload, scheme).0;
+        let score: f64 = xor::text_score(&decoded) / decoded.len().max(1) as f64;
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((scheme, score));
+        }
+    }
+    best.map(|(scheme, _)| scheme).unwrap_or_default()
+}
+
+/*
+    1 when the count of '1' bits above the least significant bit is odd.
+*/
 fn parity(byte: u8) -> u8 {
-    (((byte & 0x02) >> 1)          // 0b00000010
-        + ((byte & 0x04) >> 2)     // 0b00000100
-        + ((byte & 0x08) >> 3)     // 0b00001000
-        + ((byte & 0x10) >> 4)     // 0b00010000
-        + ((byte & 0x20) >> 5)     // 0b00100000
-        + ((byte & 0x40) >> 6)     // 0b01000000
-        + ((byte & 0x80) >> 7))    // 0b10000000
-        % 2
+    ((byte >> 1).count_ones() % 2) as u8
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::layer_two::parity;
+    use crate::layer::Layer;
+    use crate::layer_two::{
+        decode, decode_with_scheme, detect_scheme, encode, parity, LayerTwo, Parity, ParityBit,
+        ParityScheme, ParityStats,
+    };
 
     #[test]
     fn parity_test() {
@@ -100,4 +409,76 @@ mod tests {
         assert_eq!(0, parity(0b11101001));
         assert_eq!(0, parity(0b00101111));
     }
+
+    #[test]
+    fn decode_partial_group() {
+        // "Hi!" and 4 padding bits as 7 bits groups followed by a parity bit,
+        // with a byte with an incorrect parity bit at offset 2
+        let payload: Vec<u8> = vec![0b01001000, 0b00110101, 0b11111110, 0b01001000, 0b00100001];
+        let (decoded, stats) = decode(&payload);
+        assert_eq!(b"Hi!".to_vec(), decoded);
+        assert_eq!(
+            ParityStats {
+                total_bytes: 5,
+                discarded_offsets: vec![2],
+                leftover_bits: 4,
+            },
+            stats
+        );
+
+        let scheme: ParityScheme = ParityScheme::default();
+        let (peeled, summary) = LayerTwo::with_scheme(scheme)
+            .peel_with_summary(&payload)
+            .unwrap();
+        assert_eq!(decoded, peeled);
+        assert_eq!(
+            Some("even parity in the LSB, 7 data bits: 5 bytes, 1 discarded for an incorrect parity bit at offset 2, 4 leftover data bits".to_string()),
+            summary
+        );
+    }
+
+    #[test]
+    fn decode_five_data_bits() {
+        // "ok" = 01101 11101 10101 1 as 5 bits groups with parity, the high
+        // bits are ignored
+        let payload: Vec<u8> = vec![0b00011011, 0b11111010, 0b00101011, 0b00100001];
+        let scheme: ParityScheme = ParityScheme::new(Parity::Even, ParityBit::Lsb, 5).unwrap();
+        let (decoded, stats) = decode_with_scheme(&payload, scheme);
+        assert_eq!(b"ok".to_vec(), decoded);
+        assert_eq!(4, stats.leftover_bits);
+        assert_eq!(0, stats.discarded_bytes());
+    }
+
+    #[test]
+    fn scheme_test() {
+        let scheme: ParityScheme = ParityScheme::new(Parity::Odd, ParityBit::Msb, 6).unwrap();
+        // 101101 has an even count of '1' bits, odd parity sets the bit
+        assert_eq!(0b11011010, scheme.encode_byte(0b101101));
+        assert_eq!(Some(0b101101), scheme.check(0b11011011));
+        assert_eq!(None, scheme.check(0b01011010));
+        assert_eq!(0b01011010, ParityScheme::default().encode_byte(0b0101101));
+        assert_eq!(28, ParityScheme::all().len());
+        assert_eq!(None, ParityScheme::new(Parity::Even, ParityBit::Lsb, 0));
+        assert_eq!(None, ParityScheme::new(Parity::Even, ParityBit::Lsb, 8));
+    }
+
+    #[test]
+    fn detect_scheme_test() {
+        let text: &[u8] = b"==[ Layer 3/6: XOR Encryption ]=========================";
+        for scheme in ParityScheme::all().into_iter().filter(|s| s.data_bits >= 5) {
+            let mut encoded: Vec<u8> = encode(text, scheme);
+            // A byte with a flipped parity bit is discarded without changing
+            // the scheme
+            let parity_mask: u8 = match scheme.parity_bit {
+                ParityBit::Lsb => 0x01,
+                ParityBit::Msb => 0x80,
+            };
+            encoded.insert(3, scheme.encode_byte(0x15) ^ parity_mask);
+            assert_eq!(scheme, detect_scheme(&encoded));
+
+            let (decoded, stats) = decode_with_scheme(&encoded, scheme);
+            assert_eq!(text, &decoded[..text.len()]);
+            assert_eq!(vec![3], stats.discarded_offsets);
+        }
+    }
 }
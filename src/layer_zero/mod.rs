@@ -1,6 +1,6 @@
-use anyhow::{anyhow, Result};
+use crate::error::Result;
 
-use crate::helpers;
+use crate::layer::Layer;
 
 /*
 Layer 0 instructions, payload file.
@@ -24,7 +24,18 @@ solved.
 
 Decode the payload below to proceed!
  */
-pub(crate) fn decode(encoded: &str) -> Result<String> {
-    helpers::decode(encoded)
-        .and_then(|vec| String::from_utf8(vec).map_err(|e| anyhow!(e.to_string())))
+pub struct LayerZero;
+
+impl Layer for LayerZero {
+    fn name(&self) -> &'static str {
+        "ASCII85"
+    }
+
+    fn index(&self) -> usize {
+        0
+    }
+
+    fn peel(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        Ok(payload.to_vec())
+    }
 }
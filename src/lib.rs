@@ -0,0 +1,160 @@
+use document::{LayerDocument, LayerTitle};
+use dump::Dumper;
+use error::{OnionError, Result};
+use layer::Layer;
+
+pub mod ascii85;
+pub mod bits;
+pub mod builder;
+pub mod crib;
+pub mod document;
+pub mod dump;
+pub mod encoding;
+pub mod error;
+pub mod hamming;
+pub mod helpers;
+pub mod layer;
+pub mod layer_five;
+pub mod layer_four;
+pub mod layer_one;
+pub mod layer_six;
+pub mod layer_three;
+pub mod layer_two;
+pub mod layer_zero;
+pub mod packets;
+pub mod rng;
+pub mod transform;
+pub mod xor;
+
+/*
+    The result of peeling one layer of the onion: the title and instructions
+    that precede the layer's payload, the ASCII85 decoded payload, and the
+    peeled content which is the next layer (or the core after the last
+    layer). The contents are kept as bytes, use `helpers::to_text` to present
+    them. Some layers also summarize what peeling did, e.g. the bytes they
+    discarded.
+*/
+pub struct LayerOutput {
+    pub index: usize,
+    pub name: &'static str,
+    pub title: Option<LayerTitle>,
+    pub instructions: Vec<u8>,
+    pub payload: Vec<u8>,
+    pub peeled: Vec<u8>,
+    pub summary: Option<String>,
+}
+
+/*
+    Peels every registered layer of the onion, starting from the outermost
+    layer in `input`, and returns the output of each layer in order.
+*/
+pub fn peel_all(input: &[u8]) -> Result<Vec<LayerOutput>> {
+    peel_range(input, 0, layer::registry().len() - 1)
+}
+
+/*
+    Peels the layers `start` to `stop` (inclusive) of the onion, `input` must
+    be the content of layer `start`.
+*/
+pub fn peel_range(input: &[u8], start: usize, stop: usize) -> Result<Vec<LayerOutput>> {
+    peel_layers(&layer::registry(), input, start, stop, None)
+}
+
+/*
+    Same as `peel_range`, every layer is also written to disk by `dumper` as
+    soon as its payload is decoded, before the layer is peeled.
+*/
+pub fn peel_range_dumped(
+    input: &[u8],
+    start: usize,
+    stop: usize,
+    dumper: &mut Dumper,
+) -> Result<Vec<LayerOutput>> {
+    peel_layers(&layer::registry(), input, start, stop, Some(dumper))
+}
+
+/*
+    Peels the layers `start` to `stop` of the onion with the given layer
+    implementations instead of the default registry, e.g. to use a layer
+    configured with a known key. Layers are dumped when `dumper` is set.
+*/
+pub fn peel_layers(
+    registry: &[Box<dyn Layer>],
+    input: &[u8],
+    start: usize,
+    stop: usize,
+    mut dumper: Option<&mut Dumper>,
+) -> Result<Vec<LayerOutput>> {
+    let layers: Vec<&dyn Layer> = registry
+        .iter()
+        .map(|layer| layer.as_ref())
+        .filter(|layer| layer.index() >= start && layer.index() <= stop)
+        .collect();
+    if layers.is_empty() || start > stop {
+        return Err(OnionError::InvalidLayerRange { start, stop });
+    }
+
+    let mut outputs: Vec<LayerOutput> = Vec::new();
+    let mut content: Vec<u8> = input.to_vec();
+
+    for layer in layers {
+        let document: LayerDocument = LayerDocument::parse(layer.index(), &content)?;
+        let payload: Vec<u8> = helpers::decode_payload(layer.index(), &content, &document)?;
+        if let Some(dumper) = dumper.as_mut() {
+            dumper.dump_layer(layer.index(), layer.name(), document.instructions, &payload)?;
+        }
+
+        let (peeled, summary): (Vec<u8>, Option<String>) = layer.peel_with_summary(&payload)?;
+
+        outputs.push(LayerOutput {
+            index: layer.index(),
+            name: layer.name(),
+            title: document.title.clone(),
+            instructions: document.instructions.to_vec(),
+            payload,
+            peeled: peeled.clone(),
+            summary,
+        });
+        content = peeled;
+    }
+
+    if let (Some(dumper), Some(last)) = (dumper, outputs.last()) {
+        dumper.dump_output(&last.peeled)?;
+    }
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::helpers::to_text;
+    use crate::{peel_all, peel_range};
+
+    #[test]
+    fn peel_payload() {
+        let outputs = peel_all(include_bytes!("../payload")).unwrap();
+        assert_eq!(7, outputs.len());
+        assert!(to_text(&outputs[0].instructions).contains("==[ Layer 0/6: ASCII85 ]"));
+        assert!(outputs[6]
+            .instructions
+            .starts_with(b"==[ Layer 6/6: Virtual Machine ]"));
+        assert_eq!(
+            Some("Network Traffic"),
+            outputs[4].title.as_ref().map(|title| title.name.as_str())
+        );
+        assert!(outputs[6].peeled.starts_with(b"==[ The Core ]"));
+    }
+
+    #[test]
+    fn peel_range_test() {
+        let outputs = peel_all(include_bytes!("../payload")).unwrap();
+        let layer_3 = &outputs[2].peeled;
+
+        let partial = peel_range(layer_3, 3, 4).unwrap();
+        assert_eq!(2, partial.len());
+        assert_eq!(outputs[4].peeled, partial[1].peeled);
+
+        assert!(peel_range(layer_3, 4, 3).is_err());
+        assert!(peel_range(layer_3, 7, 8).is_err());
+        assert!(peel_range(layer_3, 4, 4).is_err());
+    }
+}
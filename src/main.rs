@@ -1,48 +1,323 @@
 use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process;
 
-use helpers::get_layer_start_index;
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
-mod helpers;
-mod layer_four;
-mod layer_one;
-mod layer_three;
-mod layer_two;
-mod layer_zero;
+use toms_data_onion_rust::builder::OnionBuilder;
+use toms_data_onion_rust::crib::{self, CribSession};
+use toms_data_onion_rust::document::LayerDocument;
+use toms_data_onion_rust::dump::Dumper;
+use toms_data_onion_rust::error::{OnionError, Result};
+use toms_data_onion_rust::hamming::{HammingCode, HammingLayer};
+use toms_data_onion_rust::helpers::{self, to_text};
+use toms_data_onion_rust::layer::{registry, replace, Layer};
+use toms_data_onion_rust::layer_three::LayerThree;
+use toms_data_onion_rust::transform;
+use toms_data_onion_rust::xor;
+use toms_data_onion_rust::{peel_layers, peel_range, LayerOutput};
+
+/// Peels the layers of Tom's Data Onion
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    peel: PeelArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Interactively guess the plaintext of an XOR encrypted layer
+    Crib(CribArgs),
+    /// Search for the per byte bitwise operations of a layer
+    Search(SearchArgs),
+    /// Generate an onion around a core message
+    Build(BuildArgs),
+}
+
+#[derive(Args)]
+struct PeelArgs {
+    /// Input file containing the first layer to peel, "-" reads from stdin
+    #[arg(default_value = "payload")]
+    input: String,
+
+    /// Index of the first layer to peel, the input must be this layer
+    #[arg(long, default_value_t = 0)]
+    start: usize,
+
+    /// Index of the last layer to peel
+    #[arg(long, default_value_t = 6)]
+    stop: usize,
+
+    /// What to print to stdout
+    #[arg(long, value_enum, default_value_t = Print::Peeled)]
+    print: Print,
+
+    /// Directory to write the instructions, decoded payload and a manifest
+    /// of every peeled layer to
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Key file for layer 3, as saved by the crib command, instead of
+    /// recovering the key
+    #[arg(long)]
+    xor_key: Option<PathBuf>,
+
+    /// Peel layer 2 as error correcting codewords instead of parity bytes
+    #[arg(long, value_enum)]
+    hamming: Option<Hamming>,
+}
+
+#[derive(Args)]
+struct CribArgs {
+    /// Input file containing the first layer to peel, "-" reads from stdin
+    #[arg(default_value = "payload")]
+    input: String,
+
+    /// Index of the layer the input file contains
+    #[arg(long, default_value_t = 0)]
+    start: usize,
+
+    /// Index of the XOR encrypted layer
+    #[arg(long, default_value_t = 3)]
+    layer: usize,
+
+    /// Length of the repeating key
+    #[arg(
+        long,
+        default_value_t = 32,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    key_length: usize,
+
+    /// Mark guessed bytes with square brackets instead of reverse video
+    #[arg(long)]
+    plain: bool,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// Input file containing the first layer to peel, "-" reads from stdin
+    #[arg(default_value = "payload")]
+    input: String,
+
+    /// Index of the layer the input file contains
+    #[arg(long, default_value_t = 0)]
+    start: usize,
+
+    /// Index of the layer to search
+    #[arg(long, default_value_t = 1)]
+    layer: usize,
+
+    /// Number of best transforms to print
+    #[arg(long, default_value_t = 5)]
+    count: usize,
+}
+
+#[derive(Args)]
+struct BuildArgs {
+    /// Input file containing the core message, "-" reads from stdin
+    input: String,
+
+    /// Seed of the random keys, IVs and noise, the same seed and core
+    /// always give the same onion
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// File to write the onion to instead of stdout
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Print {
+    /// The content of the next layer (or the core) after the last peeled layer
+    Peeled,
+    /// The instructions text of every peeled layer
+    Instructions,
+    /// The raw ASCII85 decoded payload bytes of the last peeled layer
+    Payload,
+    /// Print nothing
+    Nothing,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Hamming {
+    /// Hamming(7,4), corrects single bit errors
+    Hamming74,
+    /// Extended Hamming(8,4), corrects single and detects double bit errors
+    Secded,
+}
 
 fn main() {
-    let payload_file = "payload".to_string();
-    println!("Reading from input file: {}", payload_file);
-    let layer_0 = fs::read_to_string(payload_file).expect("Cannot read from input file");
-
-    let index: usize = get_layer_start_index(&*layer_0);
-    let layer_0_data = &layer_0[index..];
-
-    //println!("Content of layer 0:");
-    //println!("{}", layer_0);
-    let layer_1 = layer_zero::decode(&*layer_0_data).expect("Cannot decode layer zero");
-
-    //println!("Content of layer 1:");
-    //println!("{}", layer_1);
-    let index_layer1: usize = get_layer_start_index(&*layer_1);
-    let layer_1_data = &layer_1[index_layer1..];
-    let layer_2 = layer_one::decode(&*layer_1_data).expect("Cannot decode layer one");
-
-    //println!("Content of layer 2:");
-    //println!("{}", layer_2);
-    let index_layer2: usize = get_layer_start_index(&*layer_2);
-    let layer_2_data = &layer_2[index_layer2..];
-    let layer_3 = layer_two::decode(&*layer_2_data).expect("Cannot decode layer two");
-
-    //println!("Content of layer 3:");
-    //println!("{}", layer_3);
-    //println!("Layer3 as bytes: {:?}", layer_3.as_bytes());
-    let index_layer3: usize = get_layer_start_index(&*layer_3);
-    let layer_3_data = &layer_3[index_layer3..];
-    let layer_4 = layer_three::decode(&*layer_3_data).expect("Cannot decode layer three");
-
-    //println!("Content of layer 4:");
-    //println!("{}", layer_4);
-    let index_layer4 = get_layer_start_index(&*layer_4);
-    let layer_4_data = &layer_4[index_layer4..];
-    layer_four::decode(layer_4_data).expect("Cannot decode layer three");
+    let cli: Cli = Cli::parse();
+
+    let result: Result<()> = match &cli.command {
+        Some(Command::Crib(args)) => run_crib(args),
+        Some(Command::Search(args)) => run_search(args),
+        Some(Command::Build(args)) => run_build(args),
+        None => run(&cli.peel),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(exit_code(&e));
+    }
+}
+
+/*
+    Every category of error exits with its own code, 2 is used by clap for
+    invalid arguments.
+*/
+fn exit_code(error: &OnionError) -> i32 {
+    match error {
+        OnionError::Io(_) | OnionError::ReadInput { .. } => 3,
+        OnionError::InvalidLayerRange { .. } => 4,
+        OnionError::LayerMismatch { .. }
+        | OnionError::MissingPayloadHeader { .. }
+        | OnionError::MissingPayloadMarker { .. }
+        | OnionError::MissingPayloadTerminator { .. } => 5,
+        OnionError::UnknownEncoding { .. } | OnionError::TextDecoding { .. } => 6,
+        OnionError::MalformedPacket { .. } => 8,
+        OnionError::XorKeyNotFound { .. }
+        | OnionError::InvalidKeyLength { .. }
+        | OnionError::CribOutOfRange { .. }
+        | OnionError::CribConflict { .. }
+        | OnionError::OffsetOutOfRange { .. }
+        | OnionError::PayloadTooShort { .. }
+        | OnionError::KeyUnwrapIntegrity { .. } => 9,
+        OnionError::InvalidOpcode { .. } | OnionError::VmFault { .. } => 10,
+    }
+}
+
+fn run(args: &PeelArgs) -> Result<()> {
+    eprintln!("Reading from input file: {}", args.input);
+    let input: Vec<u8> = read_input(&args.input)?;
+
+    let mut layers: Vec<Box<dyn Layer>> = registry();
+    if let Some(xor_key) = &args.xor_key {
+        replace(
+            &mut layers,
+            Box::new(LayerThree::with_key(xor::load_key(xor_key)?)),
+        );
+    }
+
+    if let Some(hamming) = args.hamming {
+        let code: HammingCode = match hamming {
+            Hamming::Hamming74 => HammingCode::Hamming74,
+            Hamming::Secded => HammingCode::Secded,
+        };
+        replace(&mut layers, Box::new(HammingLayer::new(code)));
+    }
+
+    let mut dumper: Option<Dumper> = match &args.output_dir {
+        Some(output_dir) => Some(Dumper::new(output_dir)?),
+        None => None,
+    };
+    let outputs: Vec<LayerOutput> =
+        peel_layers(&layers, &input, args.start, args.stop, dumper.as_mut())?;
+    for output in &outputs {
+        if let Some(summary) = &output.summary {
+            eprintln!("Layer {}: {}", output.index, summary);
+        }
+    }
+    let last: &LayerOutput = outputs.last().ok_or(OnionError::InvalidLayerRange {
+        start: args.start,
+        stop: args.stop,
+    })?;
+
+    let mut stdout = io::stdout();
+    match args.print {
+        Print::Peeled => stdout.write_all(to_text(&last.peeled).as_bytes())?,
+        Print::Instructions => {
+            for output in &outputs {
+                stdout.write_all(to_text(&output.instructions).as_bytes())?;
+            }
+        }
+        Print::Payload => stdout.write_all(&last.payload)?,
+        Print::Nothing => {}
+    }
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/*
+    Peels the layers before the XOR encrypted one and starts an interactive
+    crib dragging session on its payload.
+*/
+fn run_crib(args: &CribArgs) -> Result<()> {
+    let ciphertext: Vec<u8> = layer_payload(&args.input, args.start, args.layer)?;
+    let mut session: CribSession = CribSession::new(ciphertext, args.key_length, !args.plain)?;
+
+    let stdin = io::stdin();
+    crib::run(&mut session, stdin.lock(), &mut io::stdout())
+}
+
+/*
+    Peels the layers before the searched one and prints the transforms that
+    best turn its payload into text.
+*/
+fn run_search(args: &SearchArgs) -> Result<()> {
+    let payload: Vec<u8> = layer_payload(&args.input, args.start, args.layer)?;
+    for candidate in transform::search(&payload, args.count) {
+        println!("{:8.4}  {}", candidate.score, candidate.transform);
+    }
+    Ok(())
+}
+
+/*
+    Builds an onion around the core read from the input, it can be peeled
+    like the original payload.
+*/
+fn run_build(args: &BuildArgs) -> Result<()> {
+    eprintln!("Reading from input file: {}", args.input);
+    let core: Vec<u8> = read_input(&args.input)?;
+    let onion: Vec<u8> = OnionBuilder::new(args.seed).build(&core);
+
+    match &args.output {
+        Some(output) => fs::write(output, onion)?,
+        None => {
+            let mut stdout = io::stdout();
+            stdout.write_all(&onion)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/*
+    The decoded payload of `layer`, peeling the layers before it from the
+    input, which contains layer `start`.
+*/
+fn layer_payload(input: &str, start: usize, layer: usize) -> Result<Vec<u8>> {
+    eprintln!("Reading from input file: {}", input);
+    let mut content: Vec<u8> = read_input(input)?;
+    if layer > start {
+        let mut outputs: Vec<LayerOutput> = peel_range(&content, start, layer - 1)?;
+        content = outputs
+            .pop()
+            .map(|output| output.peeled)
+            .unwrap_or_default();
+    }
+
+    let document: LayerDocument = LayerDocument::parse(layer, &content)?;
+    helpers::decode_payload(layer, &content, &document)
+}
+
+fn read_input(input: &str) -> Result<Vec<u8>> {
+    let read: io::Result<Vec<u8>> = if input == "-" {
+        let mut content: Vec<u8> = Vec::new();
+        io::stdin().read_to_end(&mut content).map(|_| content)
+    } else {
+        fs::read(input)
+    };
+    read.map_err(|source| OnionError::ReadInput {
+        path: input.to_string(),
+        source,
+    })
 }
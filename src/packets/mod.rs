@@ -0,0 +1,300 @@
+use crate::rng::Rng;
+
+/*
+    Streams of IPv4 packets carrying UDP datagrams like the payload of layer
+    4: the data is split in packets with correct checksums, and decoys that
+    break one of the rules of the layer are interleaved with them.
+*/
+
+pub(crate) const IPV4_HEADER_LEN: usize = 20;
+pub(crate) const UDP_HEADER_LEN: usize = 8;
+pub(crate) const UDP_PROTOCOL: u8 = 17;
+// The packets of the data are sent from this address to this address and port
+pub const SOURCE_ADDRESS: [u8; 4] = [10, 1, 1, 10];
+pub const DESTINATION_ADDRESS: [u8; 4] = [10, 1, 1, 200];
+pub const DESTINATION_PORT: u16 = 42069;
+
+/*
+    A packet that must be discarded, each one breaks a single rule.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decoy {
+    // Sent from another address than 10.1.1.10
+    WrongSource,
+    // Sent to another port than 42069
+    WrongDestinationPort,
+    // The IPv4 header checksum has a flipped bit
+    BadIpv4Checksum,
+    // The UDP checksum has a flipped bit
+    BadUdpChecksum,
+    // The UDP header claims more data than the packet holds
+    Truncated,
+}
+
+impl Decoy {
+    pub fn all() -> [Decoy; 5] {
+        [
+            Decoy::WrongSource,
+            Decoy::WrongDestinationPort,
+            Decoy::BadIpv4Checksum,
+            Decoy::BadUdpChecksum,
+            Decoy::Truncated,
+        ]
+    }
+}
+
+pub struct StreamOptions {
+    // Largest data section of a packet, data and decoys alike
+    pub max_packet_data: usize,
+    // Kinds of decoys to pick from, no decoys when empty
+    pub decoys: Vec<Decoy>,
+    // Chance in percent that a decoy is inserted before each packet of data
+    pub decoy_percent: usize,
+}
+
+impl Default for StreamOptions {
+    fn default() -> StreamOptions {
+        StreamOptions {
+            max_packet_data: 512,
+            decoys: Decoy::all().to_vec(),
+            decoy_percent: 25,
+        }
+    }
+}
+
+/*
+    A stream that layer 4 peels to `data`. Packets are sent from 10.1.1.10 to
+    10.1.1.200:42069 with random source ports and random sizes, decoys carry
+    random data.
+*/
+pub fn generate(data: &[u8], options: &StreamOptions, rng: &mut Rng) -> Vec<u8> {
+    let max_packet_data: usize = options.max_packet_data.max(1);
+    let mut stream: Vec<u8> = Vec::with_capacity(data.len() + data.len() / 8);
+    let mut offset: usize = 0;
+    while offset < data.len() {
+        if !options.decoys.is_empty() && rng.below(100) < options.decoy_percent {
+            let decoy: Decoy = options.decoys[rng.below(options.decoys.len())];
+            let decoy_length: usize = 1 + rng.below(max_packet_data);
+            let decoy_data: Vec<u8> = rng.bytes(decoy_length);
+            stream.extend_from_slice(&decoy_packet(decoy, &decoy_data, rng));
+        }
+
+        let length: usize = (1 + rng.below(max_packet_data)).min(data.len() - offset);
+        stream.extend_from_slice(&build_packet(
+            SOURCE_ADDRESS,
+            DESTINATION_ADDRESS,
+            source_port(rng),
+            DESTINATION_PORT,
+            &data[offset..offset + length],
+        ));
+        offset += length;
+    }
+    stream
+}
+
+/*
+    A packet carrying `data` that breaks the rule of `decoy`, and only that
+    one. `data` must not be empty for a truncated packet.
+*/
+pub fn decoy_packet(decoy: Decoy, data: &[u8], rng: &mut Rng) -> Vec<u8> {
+    let mut source: [u8; 4] = SOURCE_ADDRESS;
+    let mut destination_port: u16 = DESTINATION_PORT;
+    let mut length: usize = data.len();
+    match decoy {
+        Decoy::WrongSource => {
+            while source == SOURCE_ADDRESS {
+                source = [10, 1, 1, rng.next_u8()];
+            }
+        }
+        Decoy::WrongDestinationPort => {
+            while destination_port == DESTINATION_PORT {
+                destination_port = rng.below(0x10000) as u16;
+            }
+        }
+        Decoy::Truncated => length = rng.below(data.len()),
+        Decoy::BadIpv4Checksum | Decoy::BadUdpChecksum => {}
+    }
+
+    let mut packet: Vec<u8> = build_packet(
+        source,
+        DESTINATION_ADDRESS,
+        source_port(rng),
+        destination_port,
+        &data[..length],
+    );
+    match decoy {
+        // A single flipped bit changes the one's complement sum, so the
+        // checksum is wrong, unless it turns the UDP checksum into 0 which
+        // means no checksum
+        Decoy::BadIpv4Checksum => flip_bit(&mut packet, 10, rng.below(16)),
+        Decoy::BadUdpChecksum => {
+            let offset: usize = IPV4_HEADER_LEN + 6;
+            let mut bit: usize = rng.below(16);
+            if u16::from_be_bytes([packet[offset], packet[offset + 1]]) == 1 << bit {
+                bit = (bit + 1) % 16;
+            }
+            flip_bit(&mut packet, offset, bit);
+        }
+        // The UDP length still counts the bytes cut from the data, the
+        // checksum is computed again so that only the length is wrong
+        Decoy::Truncated => {
+            let udp_length: u16 = (UDP_HEADER_LEN + data.len()) as u16;
+            packet[IPV4_HEADER_LEN + 4..IPV4_HEADER_LEN + 6]
+                .copy_from_slice(&udp_length.to_be_bytes());
+            packet[IPV4_HEADER_LEN + 6..IPV4_HEADER_LEN + 8].copy_from_slice(&[0x00, 0x00]);
+            set_udp_checksum(&mut packet);
+        }
+        Decoy::WrongSource | Decoy::WrongDestinationPort => {}
+    }
+    packet
+}
+
+/*
+    An IPv4 packet carrying a UDP datagram, with correct checksums.
+*/
+pub fn build_packet(
+    source: [u8; 4],
+    destination: [u8; 4],
+    source_port: u16,
+    destination_port: u16,
+    data: &[u8],
+) -> Vec<u8> {
+    let udp_length: usize = UDP_HEADER_LEN + data.len();
+    let total_length: usize = IPV4_HEADER_LEN + udp_length;
+
+    let mut packet: Vec<u8> = Vec::with_capacity(total_length);
+    // Version 4 with a 5 words header, no type of service
+    packet.extend_from_slice(&[0x45, 0x00]);
+    packet.extend_from_slice(&(total_length as u16).to_be_bytes());
+    // Identification, don't fragment, TTL 64
+    packet.extend_from_slice(&[0x00, 0x00, 0x40, 0x00, 0x40, UDP_PROTOCOL, 0x00, 0x00]);
+    packet.extend_from_slice(&source);
+    packet.extend_from_slice(&destination);
+    let ipv4_checksum: u16 = !ones_complement_sum(0, &packet);
+    packet[10..12].copy_from_slice(&ipv4_checksum.to_be_bytes());
+
+    packet.extend_from_slice(&source_port.to_be_bytes());
+    packet.extend_from_slice(&destination_port.to_be_bytes());
+    packet.extend_from_slice(&(udp_length as u16).to_be_bytes());
+    packet.extend_from_slice(&[0x00, 0x00]);
+    packet.extend_from_slice(data);
+    set_udp_checksum(&mut packet);
+    packet
+}
+
+/*
+    Computes the UDP checksum of a packet with a zero checksum field. The
+    pseudo header counts the bytes of the UDP segment, like layer 4 does
+    when it checks the checksum, rather than the UDP length field.
+*/
+fn set_udp_checksum(packet: &mut [u8]) {
+    let udp_length: usize = packet.len() - IPV4_HEADER_LEN;
+    let mut pseudo_header: Vec<u8> = Vec::with_capacity(12);
+    pseudo_header.extend_from_slice(&packet[12..20]);
+    pseudo_header.push(0);
+    pseudo_header.push(UDP_PROTOCOL);
+    pseudo_header.extend_from_slice(&(udp_length as u16).to_be_bytes());
+    let sum: u16 = ones_complement_sum(
+        ones_complement_sum(0, &pseudo_header),
+        &packet[IPV4_HEADER_LEN..],
+    );
+    // A computed checksum of zero is sent as 0xFFFF, zero means no checksum
+    let udp_checksum: u16 = match !sum {
+        0 => 0xFFFF,
+        checksum => checksum,
+    };
+    packet[IPV4_HEADER_LEN + 6..IPV4_HEADER_LEN + 8].copy_from_slice(&udp_checksum.to_be_bytes());
+}
+
+fn source_port(rng: &mut Rng) -> u16 {
+    1024 + rng.below(0x10000 - 1024) as u16
+}
+
+/*
+    Flips bit `bit` of the big endian word at `offset`.
+*/
+fn flip_bit(packet: &mut [u8], offset: usize, bit: usize) {
+    let word: u16 = u16::from_be_bytes([packet[offset], packet[offset + 1]]) ^ (1 << bit);
+    packet[offset..offset + 2].copy_from_slice(&word.to_be_bytes());
+}
+
+/*
+    One's complement sum of 16 bits big endian words, an odd trailing byte
+    is padded with a zero byte.
+*/
+pub(crate) fn ones_complement_sum(initial: u16, bytes: &[u8]) -> u16 {
+    let mut sum: u32 = initial as u32;
+    for word in bytes.chunks(2) {
+        let high: u32 = (word[0] as u32) << 8;
+        let low: u32 = word.get(1).copied().unwrap_or(0) as u32;
+        sum += high | low;
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layer::Layer;
+    use crate::layer_four::LayerFour;
+    use crate::packets::{
+        build_packet, decoy_packet, generate, ones_complement_sum, Decoy, StreamOptions,
+    };
+    use crate::rng::Rng;
+
+    #[test]
+    fn build_packet_test() {
+        // Example header from https://en.wikipedia.org/wiki/IPv4_header_checksum
+        // with a UDP segment carrying "abc"
+        let packet: Vec<u8> =
+            build_packet([192, 168, 0, 1], [192, 168, 0, 199], 12345, 42069, b"abc");
+        assert_eq!(
+            vec![
+                0x45, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0xb5, 0xc0, 0xa8,
+                0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7, 0x30, 0x39, 0xa4, 0x55, 0x00, 0x0b, 0xe4, 0xcd,
+                0x61, 0x62, 0x63,
+            ],
+            packet
+        );
+        assert_eq!(0xFFFF, ones_complement_sum(0, &packet[..20]));
+    }
+
+    #[test]
+    fn decoy_test() {
+        let mut rng: Rng = Rng::new(9);
+        for decoy in Decoy::all().iter() {
+            for _ in 0..200 {
+                let length: usize = 1 + rng.below(64);
+                let data: Vec<u8> = rng.bytes(length);
+                let packet: Vec<u8> = decoy_packet(*decoy, &data, &mut rng);
+                assert!(LayerFour.peel(&packet).unwrap().is_empty(), "{:?}", decoy);
+            }
+        }
+    }
+
+    #[test]
+    fn generate_test() {
+        let data: Vec<u8> = (0..20000u32).map(|i| (i % 253) as u8).collect();
+        let clean: StreamOptions = StreamOptions {
+            decoys: Vec::new(),
+            ..StreamOptions::default()
+        };
+        let stream: Vec<u8> = generate(&data, &clean, &mut Rng::new(1));
+        assert_eq!(data, LayerFour.peel(&stream).unwrap());
+
+        for decoy in Decoy::all().iter() {
+            let options: StreamOptions = StreamOptions {
+                decoys: vec![*decoy],
+                decoy_percent: 50,
+                ..StreamOptions::default()
+            };
+            let stream: Vec<u8> = generate(&data, &options, &mut Rng::new(1));
+            assert_eq!(data, LayerFour.peel(&stream).unwrap(), "{:?}", decoy);
+        }
+
+        let stream: Vec<u8> = generate(&data, &StreamOptions::default(), &mut Rng::new(2));
+        assert_eq!(data, LayerFour.peel(&stream).unwrap());
+    }
+}
@@ -0,0 +1,54 @@
+/*
+    SplitMix64, a small seedable pseudorandom number generator: the same
+    seed always gives the same numbers, so generated data can be used as a
+    test fixture. Not suitable for real keys.
+*/
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z: u64 = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /*
+        A number from 0 to `bound` excluded, `bound` must not be 0.
+    */
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    pub fn bytes(&mut self, length: usize) -> Vec<u8> {
+        (0..length).map(|_| self.next_u8()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rng::Rng;
+
+    #[test]
+    fn rng_test() {
+        // First output of the reference implementation for the seed 0
+        assert_eq!(0xE220_A839_7B1D_CDAF, Rng::new(0).next_u64());
+
+        let mut rng: Rng = Rng::new(42);
+        let bytes: Vec<u8> = rng.bytes(16);
+        assert_eq!(bytes, Rng::new(42).bytes(16));
+        assert_ne!(bytes, rng.bytes(16));
+        assert!((0..100).all(|_| rng.below(7) < 7));
+    }
+}
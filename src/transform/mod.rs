@@ -0,0 +1,412 @@
+use std::fmt;
+
+use crate::xor;
+
+/*
+    An invertible operation on a single byte.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ByteOp {
+    Xor(u8),
+    RotateLeft(u32),
+    RotateRight(u32),
+    ReverseBits,
+    SwapNibbles,
+    Not,
+    Add(u8),
+    Sub(u8),
+}
+
+impl ByteOp {
+    pub fn apply(&self, byte: u8) -> u8 {
+        match *self {
+            ByteOp::Xor(mask) => byte ^ mask,
+            ByteOp::RotateLeft(n) => byte.rotate_left(n),
+            ByteOp::RotateRight(n) => byte.rotate_right(n),
+            ByteOp::ReverseBits => byte.reverse_bits(),
+            ByteOp::SwapNibbles => byte.rotate_left(4),
+            ByteOp::Not => !byte,
+            ByteOp::Add(value) => byte.wrapping_add(value),
+            ByteOp::Sub(value) => byte.wrapping_sub(value),
+        }
+    }
+
+    pub fn inverse(&self) -> ByteOp {
+        match *self {
+            ByteOp::RotateLeft(n) => ByteOp::RotateRight(n),
+            ByteOp::RotateRight(n) => ByteOp::RotateLeft(n),
+            ByteOp::Add(value) => ByteOp::Sub(value),
+            ByteOp::Sub(value) => ByteOp::Add(value),
+            op => op,
+        }
+    }
+}
+
+impl fmt::Display for ByteOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteOp::Xor(mask) => write!(f, "xor 0x{:02x}", mask),
+            ByteOp::RotateLeft(n) => write!(f, "rotl {}", n),
+            ByteOp::RotateRight(n) => write!(f, "rotr {}", n),
+            ByteOp::ReverseBits => write!(f, "reverse"),
+            ByteOp::SwapNibbles => write!(f, "swap nibbles"),
+            ByteOp::Not => write!(f, "not"),
+            ByteOp::Add(value) => write!(f, "add {}", value),
+            ByteOp::Sub(value) => write!(f, "sub {}", value),
+        }
+    }
+}
+
+/*
+    A pipeline of byte operations applied in order to every byte.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transform {
+    ops: Vec<ByteOp>,
+}
+
+impl Transform {
+    pub fn new(ops: Vec<ByteOp>) -> Transform {
+        Transform { ops }
+    }
+
+    pub fn then(mut self, op: ByteOp) -> Transform {
+        self.ops.push(op);
+        self
+    }
+
+    pub fn ops(&self) -> &[ByteOp] {
+        &self.ops
+    }
+
+    pub fn apply_byte(&self, byte: u8) -> u8 {
+        self.ops.iter().fold(byte, |byte, op| op.apply(byte))
+    }
+
+    /*
+        Applies every operation to every byte, one at a time.
+    */
+    pub fn apply_scalar(&self, data: &[u8]) -> Vec<u8> {
+        data.iter().map(|byte| self.apply_byte(*byte)).collect()
+    }
+
+    pub fn table(&self) -> ByteTable {
+        let mut table: [u8; 256] = [0; 256];
+        for (byte, entry) in table.iter_mut().enumerate() {
+            *entry = self.apply_byte(byte as u8);
+        }
+        ByteTable { table }
+    }
+
+    /*
+        The fastest way available: 16 bytes at a time with SSE2 on x86_64,
+        the bytes left and other targets go through a lookup table.
+    */
+    pub fn apply(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = data.to_vec();
+        self.apply_in_place(&mut output);
+        output
+    }
+
+    pub fn apply_in_place(&self, data: &mut [u8]) {
+        #[cfg(target_arch = "x86_64")]
+        let data: &mut [u8] = sse2::apply_in_place(&self.ops, data);
+        self.table().apply_in_place(data);
+    }
+
+    /*
+        The pipeline undoing this one: the inverse of every operation, in
+        reverse order.
+    */
+    pub fn inverse(&self) -> Transform {
+        Transform::new(self.ops.iter().rev().map(ByteOp::inverse).collect())
+    }
+}
+
+/*
+    The result of a transform for every byte value.
+*/
+pub struct ByteTable {
+    table: [u8; 256],
+}
+
+impl ByteTable {
+    pub fn lookup(&self, byte: u8) -> u8 {
+        self.table[byte as usize]
+    }
+
+    pub fn apply(&self, data: &[u8]) -> Vec<u8> {
+        data.iter().map(|byte| self.lookup(*byte)).collect()
+    }
+
+    pub fn apply_in_place(&self, data: &mut [u8]) {
+        data.iter_mut().for_each(|byte| *byte = self.lookup(*byte));
+    }
+}
+
+/*
+    SSE2 is part of every x86_64 processor, so it is used without checking
+    for it at runtime. Bytes are shifted within 16 bits lanes, the bits moved
+    across bytes are masked out.
+*/
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use std::arch::x86_64::*;
+
+    use crate::transform::ByteOp;
+
+    // Every operation is applied to a chunk small enough to stay in cache
+    // before the next one, instead of dispatching on each operation for
+    // every block
+    const CHUNK_SIZE: usize = 4096;
+
+    /*
+        Transforms every whole 16 bytes block of `data` and returns the bytes
+        left after them.
+    */
+    pub(super) fn apply_in_place<'a>(ops: &[ByteOp], data: &'a mut [u8]) -> &'a mut [u8] {
+        let whole: usize = data.len() / 16 * 16;
+        let (blocks, remainder) = data.split_at_mut(whole);
+        for chunk in blocks.chunks_mut(CHUNK_SIZE) {
+            for op in ops {
+                // SAFETY: SSE2 is always available on x86_64
+                unsafe { apply_op(*op, chunk) };
+            }
+        }
+        remainder
+    }
+
+    /*
+        `chunk` is a whole number of 16 bytes blocks.
+    */
+    #[inline(always)]
+    unsafe fn apply_op(op: ByteOp, chunk: &mut [u8]) {
+        match op {
+            ByteOp::Xor(mask) => {
+                let mask: __m128i = _mm_set1_epi8(mask as i8);
+                map_blocks(chunk, |vector| _mm_xor_si128(vector, mask))
+            }
+            ByteOp::RotateLeft(n) => map_blocks(chunk, |vector| rotate_left(vector, n % 8)),
+            ByteOp::RotateRight(n) => {
+                map_blocks(chunk, |vector| rotate_left(vector, (8 - n % 8) % 8))
+            }
+            ByteOp::ReverseBits => map_blocks(chunk, |vector| reverse_bits(vector)),
+            ByteOp::SwapNibbles => map_blocks(chunk, |vector| rotate_left(vector, 4)),
+            ByteOp::Not => {
+                let mask: __m128i = _mm_set1_epi8(-1);
+                map_blocks(chunk, |vector| _mm_xor_si128(vector, mask))
+            }
+            ByteOp::Add(value) => {
+                let value: __m128i = _mm_set1_epi8(value as i8);
+                map_blocks(chunk, |vector| _mm_add_epi8(vector, value))
+            }
+            ByteOp::Sub(value) => {
+                let value: __m128i = _mm_set1_epi8(value as i8);
+                map_blocks(chunk, |vector| _mm_sub_epi8(vector, value))
+            }
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn map_blocks(chunk: &mut [u8], map: impl Fn(__m128i) -> __m128i) {
+        for block in chunk.chunks_exact_mut(16) {
+            // The pointer points to 16 bytes, unaligned loads and stores are
+            // used
+            let pointer: *mut __m128i = block.as_mut_ptr() as *mut __m128i;
+            _mm_storeu_si128(pointer, map(_mm_loadu_si128(pointer)));
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn rotate_left(vector: __m128i, n: u32) -> __m128i {
+        if n == 0 {
+            return vector;
+        }
+        let left: __m128i = _mm_and_si128(
+            _mm_sll_epi16(vector, _mm_cvtsi32_si128(n as i32)),
+            _mm_set1_epi8((0xFFu8 << n) as i8),
+        );
+        let right: __m128i = _mm_and_si128(
+            _mm_srl_epi16(vector, _mm_cvtsi32_si128(8 - n as i32)),
+            _mm_set1_epi8((0xFFu8 >> (8 - n)) as i8),
+        );
+        _mm_or_si128(left, right)
+    }
+
+    /*
+        Swaps the nibbles, then the pairs of bits, then the bits.
+    */
+    #[inline(always)]
+    unsafe fn reverse_bits(vector: __m128i) -> __m128i {
+        let vector: __m128i = rotate_left(vector, 4);
+        let vector: __m128i = swap_bits(vector, 0xCC, 2);
+        swap_bits(vector, 0xAA, 1)
+    }
+
+    #[inline(always)]
+    unsafe fn swap_bits(vector: __m128i, high_mask: u8, shift: i32) -> __m128i {
+        let high: __m128i = _mm_and_si128(vector, _mm_set1_epi8(high_mask as i8));
+        let low: __m128i = _mm_andnot_si128(_mm_set1_epi8(high_mask as i8), vector);
+        _mm_or_si128(
+            _mm_srl_epi16(high, _mm_cvtsi32_si128(shift)),
+            _mm_sll_epi16(low, _mm_cvtsi32_si128(shift)),
+        )
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ops.is_empty() {
+            return write!(f, "identity");
+        }
+        for (i, op) in self.ops.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", op)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct TransformCandidate {
+    pub transform: Transform,
+    pub score: f64,
+}
+
+/*
+    Tries every combination of an optional bit reversal, a xor mask and a
+    right rotation on `data`, which covers them in any order, and returns the
+    `count` best transforms ranked by how much their output looks like
+    English text, as an average score per byte.
+*/
+pub fn search(data: &[u8], count: usize) -> Vec<TransformCandidate> {
+    let mut histogram: [usize; 256] = [0; 256];
+    for byte in data {
+        histogram[*byte as usize] += 1;
+    }
+
+    let mut candidates: Vec<TransformCandidate> = Vec::new();
+    for reverse in [false, true] {
+        for mask in 0..=255 {
+            for rotation in 0..8 {
+                let mut transform: Transform = Transform::default();
+                if reverse {
+                    transform = transform.then(ByteOp::ReverseBits);
+                }
+                if mask != 0 {
+                    transform = transform.then(ByteOp::Xor(mask));
+                }
+                if rotation != 0 {
+                    transform = transform.then(ByteOp::RotateRight(rotation));
+                }
+
+                let total: f64 = (0..=255)
+                    .filter(|byte| histogram[*byte as usize] > 0)
+                    .map(|byte| {
+                        let score: f64 =
+                            xor::character_score(transform.apply_byte(byte)).unwrap_or(-1.0);
+                        score * histogram[byte as usize] as f64
+                    })
+                    .sum();
+                candidates.push(TransformCandidate {
+                    transform,
+                    score: total / data.len().max(1) as f64,
+                });
+            }
+        }
+    }
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates.truncate(count);
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transform::{search, ByteOp, Transform};
+
+    #[test]
+    fn byte_op_test() {
+        assert_eq!(0b01101000, ByteOp::RotateLeft(3).apply(0b00001101));
+        assert_eq!(0b10100001, ByteOp::RotateRight(3).apply(0b00001101));
+        assert_eq!(0b10110000, ByteOp::ReverseBits.apply(0b00001101));
+        assert_eq!(0xD0, ByteOp::SwapNibbles.apply(0x0D));
+        assert_eq!(0xF2, ByteOp::Not.apply(0x0D));
+        assert_eq!(0x02, ByteOp::Add(0xF5).apply(0x0D));
+        assert_eq!(0xFF, ByteOp::Sub(0x0E).apply(0x0D));
+    }
+
+    #[test]
+    fn inverse_test() {
+        let transform: Transform = Transform::default()
+            .then(ByteOp::Add(17))
+            .then(ByteOp::RotateLeft(3))
+            .then(ByteOp::Xor(0xA5))
+            .then(ByteOp::ReverseBits)
+            .then(ByteOp::SwapNibbles)
+            .then(ByteOp::Not)
+            .then(ByteOp::Sub(200))
+            .then(ByteOp::RotateRight(9));
+        let inverse: Transform = transform.inverse();
+        for byte in 0..=255 {
+            assert_eq!(byte, inverse.apply_byte(transform.apply_byte(byte)));
+        }
+        assert_eq!(
+            "rotl 9, add 200, not, swap nibbles, reverse, xor 0xa5, rotr 3, sub 17",
+            inverse.to_string()
+        );
+        assert_eq!("identity", Transform::default().to_string());
+    }
+
+    #[test]
+    fn apply_paths_test() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 + i / 256) as u8).collect();
+        let ops: Vec<ByteOp> = vec![
+            ByteOp::Xor(0x55),
+            ByteOp::RotateLeft(3),
+            ByteOp::RotateRight(1),
+            ByteOp::RotateRight(8),
+            ByteOp::ReverseBits,
+            ByteOp::SwapNibbles,
+            ByteOp::Not,
+            ByteOp::Add(200),
+            ByteOp::Sub(3),
+        ];
+        for op in &ops {
+            let transform: Transform = Transform::new(vec![*op]);
+            assert_eq!(
+                transform.apply_scalar(&data),
+                transform.apply(&data),
+                "{}",
+                op
+            );
+            assert_eq!(
+                transform.apply_scalar(&data),
+                transform.table().apply(&data)
+            );
+        }
+        let transform: Transform = Transform::new(ops);
+        assert_eq!(transform.apply_scalar(&data), transform.apply(&data));
+    }
+
+    #[test]
+    fn search_test() {
+        let text: &[u8] = b"Transforms every byte of the payload with the same few \
+            bitwise operations, which are found by trying all of them.\n";
+        let encoded: Vec<u8> = Transform::default()
+            .then(ByteOp::RotateLeft(5))
+            .then(ByteOp::Xor(0x3C))
+            .then(ByteOp::ReverseBits)
+            .apply(text);
+
+        let candidates = search(&encoded, 3);
+        assert_eq!(3, candidates.len());
+        assert!(candidates[0].score > candidates[1].score);
+        assert_eq!(text.to_vec(), candidates[0].transform.apply(&encoded));
+        assert_eq!(
+            "reverse, xor 0x3c, rotr 5",
+            candidates[0].transform.to_string()
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::error::Result;
+
+/*
+    Recovery of repeating XOR keys from ciphertext whose plaintext is
+    English text, like the layers of the onion.
+*/
+
+/*
+    A piece of plaintext that is known (or guessed) to be at `offset`.
+*/
+pub struct Crib {
+    pub offset: usize,
+    pub plaintext: Vec<u8>,
+}
+
+impl Crib {
+    pub fn new(offset: usize, plaintext: &[u8]) -> Crib {
+        Crib {
+            offset,
+            plaintext: plaintext.to_vec(),
+        }
+    }
+}
+
+/*
+    A recovered key, `confidence[i]` is between 0 and 1 and tells how sure
+    the solver is about `bytes[i]`. Key bytes derived from a crib have a
+    confidence of 1.
+*/
+#[derive(Debug)]
+pub struct RecoveredKey {
+    pub bytes: Vec<u8>,
+    pub confidence: Vec<f64>,
+}
+
+/*
+    A candidate length for a repeating key. `hamming` is the mean bit
+    distance between consecutive key length blocks, normalized per bit, and
+    `coincidence` the mean index of coincidence of the key columns. Both are
+    computed on the ciphertext: with the right length, blocks and columns
+    behave like the plaintext (low distance, high coincidence) rather than
+    like random bytes.
+*/
+#[derive(Debug)]
+pub struct KeyLengthCandidate {
+    pub length: usize,
+    pub hamming: f64,
+    pub coincidence: f64,
+    pub score: f64,
+}
+
+/*
+    Ranks every key length from 1 to `max_length`, best candidate first.
+*/
+pub fn estimate_key_lengths(ciphertext: &[u8], max_length: usize) -> Vec<KeyLengthCandidate> {
+    let mut candidates: Vec<KeyLengthCandidate> = (1..=max_length)
+        .filter(|&length| ciphertext.len() >= length * 2)
+        .map(|length| {
+            let hamming: f64 = normalized_hamming_distance(ciphertext, length);
+            let coincidence: f64 = index_of_coincidence(ciphertext, length);
+            KeyLengthCandidate {
+                length,
+                hamming,
+                coincidence,
+                score: coincidence / hamming.max(f64::EPSILON),
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates
+}
+
+/*
+    Recovers a repeating key of unknown length: the `candidates` best key
+    lengths up to `max_length` are each solved with `recover_key`, and the
+    key whose decryption looks the most like English wins. Multiples of the
+    key length decrypt to the same text, so the shortest key wins a tie.
+*/
+pub fn solve(
+    ciphertext: &[u8],
+    max_length: usize,
+    candidates: usize,
+    cribs: &[Crib],
+) -> Option<RecoveredKey> {
+    let mut best: Option<(f64, RecoveredKey)> = None;
+    for candidate in estimate_key_lengths(ciphertext, max_length)
+        .iter()
+        .take(candidates)
+    {
+        let key: RecoveredKey = recover_key(ciphertext, candidate.length, cribs);
+        let score: f64 = text_score(&apply_key(ciphertext, &key.bytes));
+        let is_better: bool = match &best {
+            None => true,
+            Some((best_score, best_key)) => {
+                score > *best_score
+                    || (score == *best_score && key.bytes.len() < best_key.bytes.len())
+            }
+        };
+        if is_better {
+            best = Some((score, key));
+        }
+    }
+    best.map(|(_, key)| key)
+}
+
+/*
+    Key files hold the key as a single line of hexadecimal digits.
+*/
+pub fn save_key(path: &Path, key: &[u8]) -> Result<()> {
+    let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+    fs::write(path, hex + "\n")?;
+    Ok(())
+}
+
+pub fn load_key(path: &Path) -> Result<Vec<u8>> {
+    let content: String = fs::read_to_string(path)?;
+    parse_hex_key(content.trim()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a hexadecimal key", path.display()),
+        )
+        .into()
+    })
+}
+
+fn parse_hex_key(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() || !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/*
+    XORs `data` with the cycled `key`.
+*/
+pub fn apply_key(data: &[u8], key: &[u8]) -> Vec<u8> {
+    data.iter()
+        .zip(key.iter().cycle())
+        .map(|(byte, key_byte)| byte ^ key_byte)
+        .collect()
+}
+
+/*
+    Recovers a repeating key of `key_length` bytes. Key bytes covered by a
+    crib are taken from it, as long as they decrypt their whole column to
+    text. Every other key byte is the one whose column decrypts to the most
+    English looking text.
+*/
+pub fn recover_key(ciphertext: &[u8], key_length: usize, cribs: &[Crib]) -> RecoveredKey {
+    let mut bytes: Vec<u8> = vec![0; key_length];
+    let mut confidence: Vec<f64> = vec![0.0; key_length];
+    if key_length == 0 {
+        return RecoveredKey { bytes, confidence };
+    }
+
+    let mut from_crib: Vec<bool> = vec![false; key_length];
+    for crib in cribs {
+        for (i, plain) in crib.plaintext.iter().enumerate() {
+            let position: usize = crib.offset + i;
+            let column: usize = position % key_length;
+            if position >= ciphertext.len() || from_crib[column] {
+                continue;
+            }
+
+            let key_byte: u8 = ciphertext[position] ^ plain;
+            if column_score(ciphertext, key_length, column, key_byte).is_some() {
+                bytes[column] = key_byte;
+                confidence[column] = 1.0;
+                from_crib[column] = true;
+            }
+        }
+    }
+
+    for column in (0..key_length).filter(|&column| !from_crib[column]) {
+        let (key_byte, column_confidence) = best_key_byte(ciphertext, key_length, column);
+        bytes[column] = key_byte;
+        confidence[column] = column_confidence;
+    }
+
+    RecoveredKey { bytes, confidence }
+}
+
+/*
+    The key byte with the best score for a column, and the confidence in it:
+    how far ahead of the runner up it is, relative to its own score.
+*/
+fn best_key_byte(ciphertext: &[u8], key_length: usize, column: usize) -> (u8, f64) {
+    let mut best: (u8, f64) = (0, f64::NEG_INFINITY);
+    let mut second: f64 = f64::NEG_INFINITY;
+    for key_byte in 0..=255u8 {
+        let score: f64 =
+            column_score(ciphertext, key_length, column, key_byte).unwrap_or(f64::NEG_INFINITY);
+        if score > best.1 {
+            second = best.1;
+            best = (key_byte, score);
+        } else if score > second {
+            second = score;
+        }
+    }
+
+    let confidence: f64 = if best.1 <= 0.0 {
+        0.0
+    } else if second <= 0.0 {
+        1.0
+    } else {
+        (best.1 - second) / best.1
+    };
+    (best.0, confidence)
+}
+
+fn normalized_hamming_distance(ciphertext: &[u8], length: usize) -> f64 {
+    let blocks: Vec<&[u8]> = ciphertext.chunks_exact(length).collect();
+    let pairs: usize = blocks.len() - 1;
+    let distance: u32 = blocks
+        .windows(2)
+        .map(|pair| {
+            pair[0]
+                .iter()
+                .zip(pair[1].iter())
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum::<u32>()
+        })
+        .sum();
+    distance as f64 / (pairs * length * 8) as f64
+}
+
+/*
+    Probability that two bytes picked at random from the same column are
+    equal, averaged over the columns.
+*/
+fn index_of_coincidence(ciphertext: &[u8], length: usize) -> f64 {
+    let mut total: f64 = 0.0;
+    for column in 0..length {
+        let mut counts: [usize; 256] = [0; 256];
+        let mut n: usize = 0;
+        for &byte in ciphertext.iter().skip(column).step_by(length) {
+            counts[byte as usize] += 1;
+            n += 1;
+        }
+        if n > 1 {
+            let pairs: usize = counts
+                .iter()
+                .map(|&count| count * count.saturating_sub(1))
+                .sum();
+            total += pairs as f64 / (n * (n - 1)) as f64;
+        }
+    }
+    total / length as f64
+}
+
+/*
+    Scores a whole text like `column_score`, characters that are not
+    printable cost a point instead of disqualifying the text.
+*/
+pub(crate) fn text_score(text: &[u8]) -> f64 {
+    text.iter()
+        .map(|&byte| character_score(byte).unwrap_or(-1.0))
+        .sum()
+}
+
+/*
+    Scores how much a column decrypted with `key_byte` looks like English
+    text, None when it decrypts to anything that is not printable ASCII or
+    whitespace.
+*/
+fn column_score(ciphertext: &[u8], key_length: usize, column: usize, key_byte: u8) -> Option<f64> {
+    ciphertext
+        .iter()
+        .skip(column)
+        .step_by(key_length)
+        .map(|byte| character_score(byte ^ key_byte))
+        .sum()
+}
+
+/*
+    Relative frequency of letters and the space in English text, other
+    printable characters get a small score.
+*/
+pub(crate) fn character_score(byte: u8) -> Option<f64> {
+    const LETTERS: [f64; 26] = [
+        0.0651, 0.0124, 0.0217, 0.0350, 0.1041, 0.0198, 0.0159, 0.0493, 0.0558, 0.0009, 0.0051,
+        0.0331, 0.0202, 0.0564, 0.0596, 0.0138, 0.0009, 0.0498, 0.0516, 0.0729, 0.0225, 0.0083,
+        0.0171, 0.0014, 0.0146, 0.0007,
+    ];
+    match byte {
+        b' ' => Some(0.1918),
+        b'a'..=b'z' => Some(LETTERS[(byte - b'a') as usize]),
+        b'A'..=b'Z' => Some(LETTERS[(byte - b'A') as usize] / 4.0),
+        b'\n' | b'=' | b'-' => Some(0.01),
+        b'0'..=b'9' | b'.' | b',' | b'\'' | b'"' => Some(0.005),
+        b'!'..=b'~' => Some(0.001),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::xor::{apply_key, estimate_key_lengths, parse_hex_key, recover_key, solve, Crib};
+
+    const PLAINTEXT: &str = "==[ Layer 3/6: XOR Encryption ]=============================
+
+Exclusive Or (XOR) is another bitwise operation. It's often
+used in cryptography to combine two sources of binary data
+-- for example, to combine binary data with a secret key,
+resulting in scrambled output data.
+
+What makes XOR useful, compared to other bitwise operations
+such as AND or OR, is that it can be reversed without losing
+any information. If you know the output and one of the
+inputs, you can determine what the other input was. It
+enables encryption algorithms to be undone, so that data can
+be decrypted back to its original state.
+";
+
+    #[test]
+    fn recover_key_test() {
+        let key: Vec<u8> = (0..16u8).map(|i| i.wrapping_mul(97) ^ 0xA5).collect();
+        let ciphertext: Vec<u8> = apply_key(PLAINTEXT.as_bytes(), &key);
+
+        let cribs = [Crib::new(0, b"==[ Layer 3/6: ")];
+        let recovered = recover_key(&ciphertext, 16, &cribs);
+        assert_eq!(key, recovered.bytes);
+        assert!(recovered.confidence[..15].iter().all(|&c| c == 1.0));
+        assert!(recovered.confidence[15] > 0.0 && recovered.confidence[15] <= 1.0);
+
+        // Without cribs, frequency analysis alone finds the key
+        assert_eq!(key, recover_key(&ciphertext, 16, &[]).bytes);
+    }
+
+    #[test]
+    fn parse_hex_key_test() {
+        assert_eq!(Some(vec![0x6c, 0x24, 0xff]), parse_hex_key("6c24FF"));
+        assert_eq!(None, parse_hex_key("6c2"));
+        assert_eq!(None, parse_hex_key("6g"));
+        assert_eq!(None, parse_hex_key(""));
+    }
+
+    #[test]
+    fn key_length_test() {
+        let key: Vec<u8> = vec![0x3C, 0x99, 0x01, 0xE7, 0x5B, 0x42, 0xD0];
+        let ciphertext: Vec<u8> = apply_key(PLAINTEXT.as_bytes(), &key);
+
+        let candidates = estimate_key_lengths(&ciphertext, 20);
+        assert_eq!(20, candidates.len());
+        assert!(candidates[..3]
+            .iter()
+            .any(|candidate| candidate.length == 7));
+
+        assert_eq!(key, solve(&ciphertext, 20, 3, &[]).unwrap().bytes);
+    }
+}